@@ -84,6 +84,16 @@ pub fn set_wallpaper_from_path(image: &str) -> Result<()>{
     Ok(())
 }
 
+pub fn get_monitors() -> Vec<super::Monitor>{
+    vec![super::Monitor::screen()]
+}
+
+/// 只有一个屏幕, 使用第一张壁纸
+pub fn set_monitor_wallpapers(wallpapers: &[(super::Monitor, String)]) -> Result<()>{
+    let (_, image) = wallpapers.first().ok_or(anyhow!("没有壁纸"))?;
+    set_wallpaper_from_path(image)
+}
+
 pub fn get_current_wallpaper() -> Result<String>{
     Err(anyhow!("获取失败"))
 }
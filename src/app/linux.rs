@@ -0,0 +1,434 @@
+use std::{path::Path, process::{Child, Command, Stdio}, sync::{Arc, Mutex}};
+use anyhow::{anyhow, Result};
+use async_std::task::block_on;
+use log::{info, warn, LevelFilter};
+
+use crate::def::APP_NAME_E;
+
+static TEMPLATE:&str = r"[Desktop Entry]
+Type=Application
+Name=--name
+Exec=--exec
+Terminal=false
+X-GNOME-Autostart-enabled=true
+";
+
+/// 本程序启动的swaybg, 换壁纸时结束并回收, 避免留下僵尸进程
+static SWAYBG: Mutex<Option<Child>> = Mutex::new(None);
+
+/// 当前桌面环境
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Desktop{
+    Gnome,
+    Kde,
+    Xfce,
+    Sway,
+    Other,
+}
+
+fn detect_desktop() -> Desktop{
+    if std::env::var("SWAYSOCK").is_ok(){
+        return Desktop::Sway;
+    }
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP")
+        .or_else(|_| std::env::var("DESKTOP_SESSION"))
+        .unwrap_or_default()
+        .to_lowercase();
+    if desktop.contains("gnome") || desktop.contains("unity") || desktop.contains("budgie"){
+        Desktop::Gnome
+    }else if desktop.contains("kde") || desktop.contains("plasma"){
+        Desktop::Kde
+    }else if desktop.contains("xfce"){
+        Desktop::Xfce
+    }else if desktop.contains("sway"){
+        Desktop::Sway
+    }else{
+        Desktop::Other
+    }
+}
+
+/// 执行命令，返回标准输出
+fn run_command(program: &str, args: &[&str]) -> Result<String>{
+    let output = Command::new(program).args(args).output()?;
+    if !output.status.success(){
+        return Err(anyhow!("{program}执行失败:{}", String::from_utf8_lossy(&output.stderr)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+pub fn open_file(path: &str){
+    if let Err(err) = Command::new("xdg-open").arg(path).spawn(){
+        warn!("打开文件失败:{path} {:?}", err);
+    }
+}
+
+pub fn get_wallpaper_file_path() -> String {
+    let wallpaper_path_name = format!( "{}/wallpaper.png", get_app_home_dir());
+    info!("wallpaper {:?}", wallpaper_path_name);
+    wallpaper_path_name
+}
+
+pub fn start_main_window(){
+    // 获取当前可执行文件的路径
+    let current_exe = std::env::current_exe().unwrap();
+
+    // 启动新进程并传递命令行参数, 窗口进程独立运行, 不等待它退出
+    if let Err(err) = Command::new(current_exe).arg("/c").spawn(){
+        warn!("主窗口启动失败:{:?}", err);
+    }
+}
+
+pub fn get_screen_size() -> (i32, i32){
+    if detect_desktop() == Desktop::Sway{
+        // "  Current mode: 2560x1440 @ 59.951 Hz"
+        if let Ok(output) = run_command("swaymsg", &["-t", "get_outputs"]){
+            let mode = output.lines().find_map(|l| l.trim().strip_prefix("Current mode: "));
+            if let Some(size) = mode.and_then(|m| m.split(' ').next()).and_then(|s| parse_size(s, "x")){
+                return size;
+            }
+        }
+    }
+    // "Screen 0: minimum 8 x 8, current 1920 x 1080, maximum 32767 x 32767"
+    if let Ok(output) = run_command("xrandr", &["--current"]){
+        let current = output.lines().next().and_then(|l| l.split("current ").nth(1));
+        if let Some(size) = current.and_then(|c| c.split(',').next()).and_then(|s| parse_size(s, " x ")){
+            return size;
+        }
+    }
+    (1920, 1080)
+}
+
+pub fn get_monitors() -> Vec<super::Monitor>{
+    let monitors = if detect_desktop() == Desktop::Sway{
+        sway_monitors()
+    }else{
+        xrandr_monitors()
+    };
+    match monitors{
+        Ok(monitors) if !monitors.is_empty() => {
+            info!("显示器:{:?}", monitors);
+            monitors
+        }
+        ret => {
+            warn!("显示器列表获取失败:{:?}", ret.err());
+            vec![super::Monitor::screen()]
+        }
+    }
+}
+
+fn sway_monitors() -> Result<Vec<super::Monitor>>{
+    parse_sway_outputs(&run_command("swaymsg", &["-t", "get_outputs", "-r"])?)
+}
+
+/// 解析swaymsg -t get_outputs的输出
+fn parse_sway_outputs(output: &str) -> Result<Vec<super::Monitor>>{
+    let outputs: serde_json::Value = serde_json::from_str(output)?;
+    let mut monitors = vec![];
+    for output in outputs.as_array().ok_or(anyhow!("swaymsg输出格式错误"))?{
+        if !output["active"].as_bool().unwrap_or(false){
+            continue;
+        }
+        // rect为缩放、旋转后的逻辑坐标, current_mode为旋转前的实际像素
+        let (rect, mode) = (&output["rect"], &output["current_mode"]);
+        let mode_size = (mode["width"].as_u64(), mode["height"].as_u64());
+        // 旋转90°/270°(包括flipped-90、flipped-270)时宽高互换
+        let transform = output["transform"].as_str().unwrap_or("normal");
+        let mode_size = if transform.ends_with("90") || transform.ends_with("270") { (mode_size.1, mode_size.0) }else{ mode_size };
+        let width = mode_size.0.or(rect["width"].as_u64()).unwrap_or(1920) as u32;
+        let rect_width = rect["width"].as_u64().unwrap_or(width as u64).max(1);
+        monitors.push(super::Monitor{
+            id: output["name"].as_str().unwrap_or_default().to_string(),
+            x: rect["x"].as_i64().unwrap_or(0) as i32,
+            y: rect["y"].as_i64().unwrap_or(0) as i32,
+            width,
+            height: mode_size.1.or(rect["height"].as_u64()).unwrap_or(1080) as u32,
+            scale: width as f64 / rect_width as f64,
+        });
+    }
+    Ok(monitors)
+}
+
+fn xrandr_monitors() -> Result<Vec<super::Monitor>>{
+    // " 0: +*DP-1 2560/597x1440/336+0+0  DP-1"
+    let output = run_command("xrandr", &["--listmonitors"])?;
+    let mut monitors = vec![];
+    for line in output.lines().skip(1){
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (Some(geometry), Some(name)) = (parts.get(2), parts.last()) else { continue };
+        let Some((w, rest)) = geometry.split_once('x') else { continue };
+        let mut rest = rest.split('+');
+        let h = rest.next().unwrap_or_default();
+        let parse = |s: &str| s.split('/').next().and_then(|v| v.parse::<i64>().ok());
+        let (Some(width), Some(height)) = (parse(w), parse(h)) else { continue };
+        monitors.push(super::Monitor{
+            id: name.to_string(),
+            x: rest.next().and_then(parse).unwrap_or(0) as i32,
+            y: rest.next().and_then(parse).unwrap_or(0) as i32,
+            width: width as u32,
+            height: height as u32,
+            scale: 1.0,
+        });
+    }
+    Ok(monitors)
+}
+
+fn parse_size(s: &str, sp: &str) -> Option<(i32, i32)>{
+    let (w, h) = s.split_once(sp)?;
+    Some((w.trim().parse().ok()?, h.trim().parse().ok()?))
+}
+
+fn get_autostart_file(app_name:&str) -> String{
+    format!("{}/autostart/{}.desktop", get_config_dir(), app_name)
+}
+
+pub fn remove_app_for_startup(app_name:&str) -> Result<()>{
+    std::fs::remove_file(get_autostart_file(app_name))?;
+    Ok(())
+}
+
+pub fn register_app_for_startup(app_name:&str) -> Result<()>{
+    let desktop_file = get_autostart_file(app_name);
+    if let Some(dir) = Path::new(&desktop_file).parent(){
+        std::fs::create_dir_all(dir)?;
+    }
+    let exe_path = ::std::env::current_exe()?;
+    if let Some(exe_path) = exe_path.to_str(){
+        let content = TEMPLATE.replace("--name", app_name).replace("--exec", &format!("\"{exe_path}\""));
+        std::fs::write(desktop_file, content)?;
+        Ok(())
+    }else{
+        Err(anyhow!("exe路径读取失败!"))
+    }
+}
+
+pub fn is_app_registered_for_startup(app_name:&str) -> Result<bool>{
+    Ok(Path::new(&get_autostart_file(app_name)).exists())
+}
+
+pub fn run() -> Result<()> {
+    env_logger::Builder::new().filter_level(LevelFilter::Info).init();
+
+    let args: Vec<String> = std::env::args().collect();
+    for (i, arg) in args.iter().enumerate().skip(1) {
+        let arg = arg.to_lowercase();
+        if arg == "/export" {
+            //导出延时动画
+            return crate::export::run_export_command(&args[i+1..]);
+        }
+        if arg.starts_with("/c") {
+            //打开设置页面
+            info!("收到 /c参数，打开窗口");
+            super::open_main_window();
+            return Ok(());
+        }
+    }
+
+    //打开窗口
+    start_main_window();
+
+    //没有托盘图标，在前台运行定时更新任务
+    let is_exit = Arc::new(Mutex::new(false));
+    block_on(crate::server::start_update_loop(is_exit));
+    Ok(())
+}
+
+pub fn get_config_dir() -> String{
+    dirs::config_dir().unwrap_or_default().to_str().unwrap_or("").to_string()
+}
+
+pub fn get_app_home_dir() -> String {
+    let mut app_home_dir = String::from(".");
+    if let Some(home_dir) = dirs::home_dir(){
+        if let Some(home_dir) = home_dir.to_str(){
+            let app_home_dir_tmp = format!("{}/{}", home_dir, APP_NAME_E);
+            if Path::exists(Path::new(&app_home_dir_tmp)){
+                app_home_dir = app_home_dir_tmp;
+            }else{
+                if let Ok(()) = std::fs::create_dir(&app_home_dir_tmp){
+                    app_home_dir = app_home_dir_tmp;
+                }
+            }
+        }
+    }
+    info!("app_home_dir {}", app_home_dir);
+    app_home_dir
+}
+
+/// 同步设置锁屏壁纸, 仅GNOME支持
+/// 结束上一次启动的swaybg并用新参数重新启动
+fn restart_swaybg(args: &[&str]) -> Result<()>{
+    let mut swaybg = SWAYBG.lock().map_err(|err| anyhow!("{:?}", err))?;
+    match swaybg.take(){
+        Some(mut child) => {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        // 第一次设置时结束sway配置中启动的swaybg, 它不是本程序的子进程
+        None => {
+            let _ = run_command("pkill", &["-x", "swaybg"]);
+        }
+    }
+    let child = Command::new("swaybg")
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    *swaybg = Some(child);
+    Ok(())
+}
+
+pub fn set_lock_screen_image(image: &str) -> Result<()>{
+    if detect_desktop() == Desktop::Gnome{
+        run_command("gsettings", &["set", "org.gnome.desktop.screensaver", "picture-uri", &format!("file://{image}")])?;
+    }
+    Ok(())
+}
+
+// 设置壁纸
+pub fn set_wallpaper_from_path(image: &str) -> Result<()>{
+    let desktop = detect_desktop();
+    info!("桌面环境:{:?}", desktop);
+    match desktop{
+        Desktop::Gnome => {
+            let uri = format!("file://{image}");
+            run_command("gsettings", &["set", "org.gnome.desktop.background", "picture-uri", &uri])?;
+            // GNOME 42+ 深色模式使用单独的设置项
+            let _ = run_command("gsettings", &["set", "org.gnome.desktop.background", "picture-uri-dark", &uri]);
+            run_command("gsettings", &["set", "org.gnome.desktop.background", "picture-options", "scaled"])?;
+        }
+        Desktop::Kde => {
+            let script = format!(r#"
+                var allDesktops = desktops();
+                for (var i = 0; i < allDesktops.length; i++) {{
+                    var d = allDesktops[i];
+                    d.wallpaperPlugin = "org.kde.image";
+                    d.currentConfigGroup = Array("Wallpaper", "org.kde.image", "General");
+                    d.writeConfig("Image", "file://{image}");
+                }}"#);
+            let args = ["org.kde.plasmashell", "/PlasmaShell", "org.kde.PlasmaShell.evaluateScript", script.as_str()];
+            run_command("qdbus", &args).or_else(|_| run_command("qdbus6", &args))?;
+        }
+        Desktop::Xfce => {
+            let props = run_command("xfconf-query", &["-c", "xfce4-desktop", "-l"])?;
+            for prop in props.lines().filter(|p| p.ends_with("/last-image")){
+                run_command("xfconf-query", &["-c", "xfce4-desktop", "-p", prop, "-s", image])?;
+            }
+        }
+        Desktop::Sway => {
+            restart_swaybg(&["-o", "*", "-i", image, "-m", "fill"])?;
+        }
+        Desktop::Other => {
+            run_command("feh", &["--bg-fill", image])?;
+        }
+    }
+    Ok(())
+}
+
+/// 为每个显示器分别设置壁纸, GNOME不支持时拼成一张图横跨所有显示器
+pub fn set_monitor_wallpapers(wallpapers: &[(super::Monitor, String)]) -> Result<()>{
+    let desktop = detect_desktop();
+    info!("桌面环境:{:?} 显示器:{}个", desktop, wallpapers.len());
+    match desktop{
+        Desktop::Sway => {
+            let mut args = vec![];
+            for (monitor, image) in wallpapers{
+                args.extend(["-o", monitor.id.as_str(), "-i", image.as_str(), "-m", "fill"]);
+            }
+            restart_swaybg(&args)?;
+        }
+        Desktop::Xfce => {
+            // /backdrop/screen0/monitorDP-1/workspace0/last-image
+            let props = run_command("xfconf-query", &["-c", "xfce4-desktop", "-l"])?;
+            for (monitor, image) in wallpapers{
+                let key = format!("/monitor{}/", monitor.id);
+                for prop in props.lines().filter(|p| p.ends_with("/last-image") && p.contains(&key)){
+                    run_command("xfconf-query", &["-c", "xfce4-desktop", "-p", prop, "-s", image])?;
+                }
+            }
+        }
+        Desktop::Kde => {
+            // Plasma按屏幕序号给每个桌面设置壁纸
+            let images: Vec<String> = wallpapers.iter().map(|(_, image)| format!("\"file://{image}\"")).collect();
+            let script = format!(r#"
+                var images = [{}];
+                var allDesktops = desktops();
+                for (var i = 0; i < allDesktops.length; i++) {{
+                    var d = allDesktops[i];
+                    d.wallpaperPlugin = "org.kde.image";
+                    d.currentConfigGroup = Array("Wallpaper", "org.kde.image", "General");
+                    d.writeConfig("Image", images[Math.min(d.screen, images.length - 1)]);
+                }}"#, images.join(","));
+            let args = ["org.kde.plasmashell", "/PlasmaShell", "org.kde.PlasmaShell.evaluateScript", script.as_str()];
+            run_command("qdbus", &args).or_else(|_| run_command("qdbus6", &args))?;
+        }
+        Desktop::Gnome => {
+            let image = stitch_wallpapers(wallpapers)?;
+            let uri = format!("file://{image}");
+            run_command("gsettings", &["set", "org.gnome.desktop.background", "picture-uri", &uri])?;
+            let _ = run_command("gsettings", &["set", "org.gnome.desktop.background", "picture-uri-dark", &uri]);
+            run_command("gsettings", &["set", "org.gnome.desktop.background", "picture-options", "spanned"])?;
+        }
+        Desktop::Other => {
+            // feh按Xinerama屏幕顺序使用每张图片
+            let mut args = vec!["--bg-fill"];
+            args.extend(wallpapers.iter().map(|(_, image)| image.as_str()));
+            run_command("feh", &args)?;
+        }
+    }
+    Ok(())
+}
+
+/// 按显示器位置把多张壁纸拼成一张覆盖整个虚拟桌面的图
+fn stitch_wallpapers(wallpapers: &[(super::Monitor, String)]) -> Result<String>{
+    let left = wallpapers.iter().map(|(m, _)| m.x).min().unwrap_or(0);
+    let top = wallpapers.iter().map(|(m, _)| m.y).min().unwrap_or(0);
+    let right = wallpapers.iter().map(|(m, _)| m.x + m.width as i32).max().unwrap_or(0);
+    let bottom = wallpapers.iter().map(|(m, _)| m.y + m.height as i32).max().unwrap_or(0);
+    let mut canvas = image::RgbImage::new((right - left).max(1) as u32, (bottom - top).max(1) as u32);
+    for (monitor, image) in wallpapers{
+        let image = image::open(image)?.to_rgb8();
+        image::imageops::replace(&mut canvas, &image, (monitor.x - left) as i64, (monitor.y - top) as i64);
+    }
+    let path = format!("{}/wallpaper_span.png", get_app_home_dir());
+    canvas.save(&path)?;
+    Ok(path)
+}
+
+pub fn get_current_wallpaper() -> Result<String>{
+    match detect_desktop(){
+        Desktop::Gnome => {
+            let uri = run_command("gsettings", &["get", "org.gnome.desktop.background", "picture-uri"])?;
+            Ok(uri.trim().trim_matches('\'').trim_start_matches("file://").to_string())
+        }
+        Desktop::Xfce => {
+            let props = run_command("xfconf-query", &["-c", "xfce4-desktop", "-l"])?;
+            let prop = props.lines().find(|p| p.ends_with("/last-image")).ok_or(anyhow!("未找到桌面壁纸设置"))?;
+            Ok(run_command("xfconf-query", &["-c", "xfce4-desktop", "-p", prop])?.trim().to_string())
+        }
+        Desktop::Other => {
+            // ~/.fehbg: feh --no-fehbg --bg-fill '/path/to/image.png'
+            let fehbg = std::fs::read_to_string(format!("{}/.fehbg", dirs::home_dir().unwrap_or_default().to_str().unwrap_or("")))?;
+            let path = fehbg.lines().last().and_then(|l| l.split('\'').nth(1)).ok_or(anyhow!("获取失败"))?;
+            Ok(path.to_string())
+        }
+        _ => Err(anyhow!("获取失败"))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn sway_rotated_output_swaps_mode_size(){
+        let output = r#"[
+            {"name":"DP-1","active":true,"transform":"normal","rect":{"x":0,"y":0,"width":1280,"height":720},"current_mode":{"width":2560,"height":1440}},
+            {"name":"DP-2","active":true,"transform":"90","rect":{"x":1280,"y":0,"width":1080,"height":1920},"current_mode":{"width":1920,"height":1080}},
+            {"name":"DP-3","active":true,"transform":"flipped-270","rect":{"x":2360,"y":0,"width":720,"height":1280},"current_mode":{"width":2560,"height":1440}},
+            {"name":"HDMI-A-1","active":false,"rect":{"x":0,"y":0,"width":0,"height":0}}
+        ]"#;
+        let monitors = parse_sway_outputs(output).unwrap();
+        let sizes: Vec<(&str, u32, u32, f64)> = monitors.iter().map(|m| (m.id.as_str(), m.width, m.height, m.scale)).collect();
+        assert_eq!(sizes, vec![("DP-1", 2560, 1440, 2.0), ("DP-2", 1080, 1920, 1.0), ("DP-3", 1440, 2560, 2.0)]);
+    }
+}
@@ -6,20 +6,36 @@ use def::APP_NAME;
 use log::warn;
 use log::{error, info};
 use slint::Rgb8Pixel;
-use slint::{Image, SharedPixelBuffer, Timer, TimerMode, Weak};
+use slint::{Image, ModelRc, SharedPixelBuffer, VecModel, Weak};
+#[cfg(not(target_os = "linux"))]
+use slint::{Timer, TimerMode};
 use async_std::sync::{Arc, Mutex};
+#[cfg(not(target_os = "linux"))]
 use time::Time;
 use crate::config::Config;
 use crate::downloader;
 use crate::def;
 use crate::downloader::is_downlading;
+use crate::downloader::composite;
+use crate::downloader::source::{parse_pinned_time, sources, PINNED_TIME_FORMAT};
+use crate::history::WallpaperHistory;
+use crate::overlay::parse_color;
+use crate::background::BackgroundStyle;
+use crate::layout::{HAnchor, LayoutConfig, PortraitSide, VAnchor};
+#[cfg(not(target_os = "linux"))]
 use crate::server;
+use crate::ui::HistoryItem;
 
 #[cfg(windows)]
 mod windows;
 #[cfg(windows)]
 pub use windows::*;
 
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+pub use linux::*;
+
 #[cfg(target_os = "android")]
 mod android;
 #[cfg(target_os = "android")]
@@ -27,6 +43,36 @@ pub use android::*;
 
 static DEFAULT_IMAGE:&[u8] = include_bytes!("../../res/icon_loading.png");
 
+/// 显示器, x、y为在虚拟桌面中的位置, 宽高为实际像素
+#[derive(Clone, Debug, PartialEq)]
+pub struct Monitor{
+    /// 系统中的显示器标识, Windows为设备路径, Linux为输出名
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// 每个桌面坐标单位对应的像素数, 桌面坐标为缩放后的逻辑坐标时大于1
+    pub scale: f64,
+}
+
+impl Monitor{
+    /// 无法获取显示器列表时, 把整个屏幕当作一个显示器
+    pub fn screen() -> Monitor{
+        let (width, height) = get_screen_size();
+        Monitor{ id: String::new(), x: 0, y: 0, width: width as u32, height: height as u32, scale: 1.0 }
+    }
+}
+
+/// 第index个显示器的壁纸文件, 第一个显示器使用wallpaper.png
+pub fn get_monitor_wallpaper_file_path(index: usize) -> String{
+    let path = get_wallpaper_file_path();
+    if index == 0{
+        return path;
+    }
+    path.replace("wallpaper.png", &format!("wallpaper_{index}.png"))
+}
+
 pub async fn open_wall_paper_image(url: &str) -> anyhow::Result<SharedPixelBuffer<Rgb8Pixel>>{
     let url = url.to_string();
     spawn_blocking(move ||{
@@ -49,6 +95,7 @@ pub async fn open_wall_paper_image(url: &str) -> anyhow::Result<SharedPixelBuffe
 }
 
 async fn update_ui(app: Weak<crate::ui::Main>, cfg: Arc<Mutex<Config>>){
+    use slint::ComponentHandle;
     loop{
         let app = match app.upgrade() {
             Some(app) => app,
@@ -62,6 +109,18 @@ async fn update_ui(app: Weak<crate::ui::Main>, cfg: Arc<Mutex<Config>>){
                 info!("tab切换，读取了配置文件...");
                 app.set_old_tab_index(current_tab_index);
                 let _ = cfg_lock.load_from_file().await;
+                //输入框只在切换页面时刷新, 避免覆盖正在输入的内容
+                app.set_pinned_time(cfg_lock.pinned_time.as_str().into());
+                app.set_days_ago(cfg_lock.days_ago.max(1) as i32);
+                app.set_layout_scale(get_layout_scale(&cfg_lock) as i32);
+                app.set_layout_padding(cfg_lock.layout.padding as i32);
+                app.set_layout_background(cfg_lock.layout.background.as_str().into());
+                app.set_layout_background_gradient(cfg_lock.layout.background_gradient.as_str().into());
+                app.set_background_image(cfg_lock.layout.background_image.as_str().into());
+                app.set_background_style_index(BACKGROUND_STYLES.iter().position(|s| *s == cfg_lock.layout.background_style).unwrap_or(0) as i32);
+                if current_tab_index == HISTORY_TAB_INDEX{
+                    let _ = slint::spawn_local(load_history(app.as_weak(), cfg_lock.clone()));
+                }
             }
             cfg_lock.clone()
         };
@@ -69,10 +128,18 @@ async fn update_ui(app: Weak<crate::ui::Main>, cfg: Arc<Mutex<Config>>){
         app.set_wallpaper_file(cfg.current_wallpaper_file.as_str().into());
         app.set_h8_data_url(cfg.download_url_h8.as_str().into());
         app.set_f4a_data_url(cfg.download_url_fy4b.as_str().into());
+        app.set_goes_data_url(cfg.download_url_goes.as_str().into());
+        app.set_meteosat_data_url(cfg.download_url_meteosat.as_str().into());
         app.set_config_file(cfg.config_path.as_str().into());
         app.set_current_interval_index(cfg.update_interval as i32/10 - 1);
         app.set_current_size_index(cfg.display_type as i32-1);
-        app.set_current_satellite_index(if cfg.current_wallpaper_date.contains("fy4b"){ 0 }else{ 1 });
+        app.set_current_satellite_index(get_satellite_index(&cfg.satellite_name));
+        app.set_current_time_mode_index(get_time_mode_index(&cfg));
+        app.set_current_overlay_index(get_overlay_index(&cfg));
+        app.set_span_monitors(cfg.span_monitors);
+        app.set_layout_horizontal_index(H_ANCHORS.iter().position(|a| *a == cfg.layout.horizontal).unwrap_or(1) as i32);
+        app.set_layout_vertical_index(V_ANCHORS.iter().position(|a| *a == cfg.layout.vertical).unwrap_or(1) as i32);
+        app.set_portrait_side_index(PORTRAIT_SIDES.iter().position(|s| *s == cfg.layout.portrait_side).unwrap_or(0) as i32);
     
         let current_wallpaper_date = app.get_current_wallpaper();
         let is_downloading = is_downlading();
@@ -107,6 +174,130 @@ async fn update_ui(app: Weak<crate::ui::Main>, cfg: Arc<Mutex<Config>>){
     }
 }
 
+/// 历史页在TabWidget中的序号
+const HISTORY_TAB_INDEX: i32 = 2;
+
+/// 历史页缩略图宽度
+const THUMBNAIL_WIDTH: u32 = 400;
+
+/// 在后台线程生成历史壁纸的缩略图, 然后显示到历史页
+async fn load_history(app: Weak<crate::ui::Main>, cfg: Config){
+    if let Some(app) = app.upgrade(){
+        app.set_history_status("正在读取历史壁纸...".into());
+    }
+    let ret = spawn_blocking(move ||{
+        let t = Instant::now();
+        let history = WallpaperHistory::from_config(&cfg).unwrap_or_default();
+        let mut items = vec![];
+        for entry in history.list()?{
+            match image::open(&entry.path){
+                Ok(img) => {
+                    let thumbnail = img.thumbnail(THUMBNAIL_WIDTH, THUMBNAIL_WIDTH).to_rgb8();
+                    let buf = SharedPixelBuffer::<Rgb8Pixel>::clone_from_slice(&thumbnail, thumbnail.width(), thumbnail.height());
+                    items.push((entry, buf));
+                }
+                Err(err) => error!("历史壁纸读取失败:{:?} {:?}", entry.path, err),
+            }
+        }
+        info!("历史壁纸缩略图生成完成 共{}张 耗时:{}ms", items.len(), t.elapsed().as_millis());
+        anyhow::Ok(items)
+    }).await;
+    let app = match app.upgrade() {
+        Some(app) => app,
+        None => return
+    };
+    match ret{
+        Ok(items) => {
+            app.set_history_status(format!("共{}张历史壁纸", items.len()).into());
+            let items: Vec<HistoryItem> = items.into_iter().map(|(entry, buf)| HistoryItem{
+                thumbnail: Image::from_rgb8(buf),
+                timestr: entry.timestr.into(),
+                path: entry.path.to_string_lossy().to_string().into(),
+            }).collect();
+            app.set_history_items(ModelRc::new(VecModel::from(items)));
+        }
+        Err(err) => {
+            error!("历史壁纸读取失败:{:?}", err);
+            app.set_history_status(format!("历史壁纸读取失败:{err}").into());
+        }
+    }
+}
+
+/// 卫星在设置页下拉框中的序号, 全球拼接排在所有卫星之后
+fn get_satellite_index(satellite_name: &str) -> i32{
+    if satellite_name == composite::NAME{
+        return sources().len() as i32;
+    }
+    sources().iter().position(|s| s.name() == satellite_name).unwrap_or(0) as i32
+}
+
+/// 文字信息位置, 和设置页下拉框的顺序一致
+const OVERLAY_CORNERS: [&str; 4] = ["top_left", "top_right", "bottom_left", "bottom_right"];
+
+/// 文字信息在设置页下拉框中的序号, 0为不显示
+fn get_overlay_index(cfg: &Config) -> i32{
+    if !cfg.overlay_enabled{
+        return 0;
+    }
+    OVERLAY_CORNERS.iter().position(|c| *c == cfg.overlay_corner).unwrap_or(3) as i32 + 1
+}
+
+/// 布局位置, 和设置页下拉框的顺序一致
+const H_ANCHORS: [HAnchor; 3] = [HAnchor::Left, HAnchor::Center, HAnchor::Right];
+const V_ANCHORS: [VAnchor; 3] = [VAnchor::Top, VAnchor::Center, VAnchor::Bottom];
+const PORTRAIT_SIDES: [PortraitSide; 3] = [PortraitSide::ByTime, PortraitSide::East, PortraitSide::West];
+const BACKGROUND_STYLES: [BackgroundStyle; 4] = [BackgroundStyle::Color, BackgroundStyle::Starfield, BackgroundStyle::Blur, BackgroundStyle::Image];
+
+/// 当前壁纸样式(整张/半张)的地球大小百分比
+fn get_layout_scale(cfg: &Config) -> u32{
+    if cfg.display_type == 2 { cfg.layout.half_scale } else { cfg.layout.scale }
+}
+
+/// 修改布局设置, 有变化时保存并立即更新壁纸
+async fn save_layout<F: FnOnce(&mut LayoutConfig)>(config: Arc<Mutex<Config>>, f: F){
+    let mut cfg = {
+        config.lock().await.clone()
+    };
+    let old = cfg.layout.clone();
+    f(&mut cfg.layout);
+    if cfg.layout == old{
+        return;
+    }
+    info!("修改布局，保存配置... {:?}", cfg.layout);
+    cfg.current_wallpaper_date = "".to_string();
+    let _ = cfg.save_to_file().await;
+    *config.lock().await = cfg.clone();
+    downloader::set_wallpaper_default(&mut cfg).await;
+}
+
+/// 壁纸时间在设置页下拉框中的序号 0:最新 1:固定时间 2:N天前
+fn get_time_mode_index(cfg: &Config) -> i32{
+    if !cfg.pinned_time.is_empty(){
+        1
+    }else if cfg.days_ago > 0{
+        2
+    }else{
+        0
+    }
+}
+
+/// 保存壁纸时间设置并立即更新壁纸
+async fn save_wallpaper_time(config: Arc<Mutex<Config>>, pinned_time: String, days_ago: u32){
+    let mut cfg = {
+        config.lock().await.clone()
+    };
+    if cfg.pinned_time == pinned_time && cfg.days_ago == days_ago{
+        return;
+    }
+    cfg.pinned_time = pinned_time;
+    cfg.days_ago = days_ago;
+    info!("修改壁纸时间，保存配置... pinned_time={} days_ago={}", cfg.pinned_time, cfg.days_ago);
+    cfg.current_wallpaper_date = "".to_string();
+    let _ = cfg.save_to_file().await;
+    *config.lock().await = cfg.clone();
+    downloader::set_wallpaper_default(&mut cfg).await;
+}
+
 pub fn open_main_window(){
     use slint::ComponentHandle;
     info!("启动窗口...");
@@ -141,10 +332,10 @@ pub fn open_main_window(){
         let config_clone = config_clone.clone();
         let _ = slint::spawn_local(async move {
             let mut cfg = config_clone.lock().await;
-            if select_index == 0{
-                cfg.satellite_name = "fy4b".to_string();
+            if let Some(source) = sources().get(select_index as usize){
+                cfg.satellite_name = source.name().to_string();
             }else{
-                cfg.satellite_name = "h8".to_string();
+                cfg.satellite_name = composite::NAME.to_string();
             }
             info!("修改卫星，保存配置...");
             cfg.current_wallpaper_date = "".to_string();
@@ -173,13 +364,19 @@ pub fn open_main_window(){
     });
 
     let config_clone = config.clone();
+    let app_clone = app.as_weak();
     app.on_change_wallpaper_size(move |select_index| {
         let config_clone = config_clone.clone();
+        let app_clone = app_clone.clone();
         let _ = slint::spawn_local(async move {
             let mut cfg = {
                 config_clone.lock().await.clone()
             };
             cfg.display_type = select_index as u32 + 1;
+            //整张和半张的地球大小分别保存
+            if let Some(app) = app_clone.upgrade(){
+                app.set_layout_scale(get_layout_scale(&cfg) as i32);
+            }
             info!("修改壁纸大小，保存配置...");
             cfg.current_wallpaper_date = "".to_string();
             let _ = cfg.save_to_file().await;
@@ -192,6 +389,226 @@ pub fn open_main_window(){
         });
     });
 
+    let config_clone = config.clone();
+    app.on_change_span_monitors(move |span| {
+        let config_clone = config_clone.clone();
+        let _ = slint::spawn_local(async move {
+            let mut cfg = {
+                config_clone.lock().await.clone()
+            };
+            cfg.span_monitors = span;
+            info!("修改多显示器模式，保存配置...");
+            cfg.current_wallpaper_date = "".to_string();
+            let _ = cfg.save_to_file().await;
+            *config_clone.lock().await = cfg.clone();
+
+            //立即更新
+            downloader::set_wallpaper_default(&mut cfg).await;
+        });
+    });
+
+    let config_clone = config.clone();
+    app.on_change_layout_scale(move |scale| {
+        let config_clone = config_clone.clone();
+        let _ = slint::spawn_local(async move {
+            let half = config_clone.lock().await.display_type == 2;
+            save_layout(config_clone, |layout|{
+                if half{
+                    layout.half_scale = scale.max(1) as u32;
+                }else{
+                    layout.scale = scale.max(1) as u32;
+                }
+            }).await;
+        });
+    });
+
+    let config_clone = config.clone();
+    app.on_change_layout_padding(move |padding| {
+        let _ = slint::spawn_local(save_layout(config_clone.clone(), move |layout| layout.padding = padding.max(0) as u32));
+    });
+
+    let config_clone = config.clone();
+    app.on_change_layout_horizontal(move |select_index| {
+        let horizontal = H_ANCHORS[(select_index as usize).min(H_ANCHORS.len() - 1)];
+        let _ = slint::spawn_local(save_layout(config_clone.clone(), move |layout| layout.horizontal = horizontal));
+    });
+
+    let config_clone = config.clone();
+    app.on_change_layout_vertical(move |select_index| {
+        let vertical = V_ANCHORS[(select_index as usize).min(V_ANCHORS.len() - 1)];
+        let _ = slint::spawn_local(save_layout(config_clone.clone(), move |layout| layout.vertical = vertical));
+    });
+
+    let config_clone = config.clone();
+    app.on_change_portrait_side(move |select_index| {
+        let side = PORTRAIT_SIDES[(select_index as usize).min(PORTRAIT_SIDES.len() - 1)];
+        let _ = slint::spawn_local(save_layout(config_clone.clone(), move |layout| layout.portrait_side = side));
+    });
+
+    let config_clone = config.clone();
+    app.on_change_layout_background(move |background, gradient| {
+        let background = background.trim().to_string();
+        let gradient = gradient.trim().to_string();
+        for color in [&background, &gradient]{
+            if !color.is_empty(){
+                if let Err(err) = parse_color(color){
+                    error!("{:?}", err);
+                    return;
+                }
+            }
+        }
+        let _ = slint::spawn_local(save_layout(config_clone.clone(), move |layout|{
+            layout.background = if background.is_empty() { String::from("#000000") } else { background };
+            layout.background_gradient = gradient;
+        }));
+    });
+
+    let config_clone = config.clone();
+    let app_clone = app.as_weak();
+    app.on_change_background_style(move |select_index| {
+        let style = BACKGROUND_STYLES[(select_index as usize).min(BACKGROUND_STYLES.len() - 1)];
+        let app = app_clone.unwrap();
+        app.set_background_style_index(select_index);
+        //自定义图片需要先填写路径
+        if style == BackgroundStyle::Image && app.get_background_image().trim().is_empty(){
+            return;
+        }
+        let _ = slint::spawn_local(save_layout(config_clone.clone(), move |layout| layout.background_style = style));
+    });
+
+    let config_clone = config.clone();
+    app.on_change_background_image(move |path| {
+        let path = path.trim().to_string();
+        if !Path::new(&path).is_file(){
+            error!("背景图片不存在:{path}");
+            return;
+        }
+        let _ = slint::spawn_local(save_layout(config_clone.clone(), move |layout|{
+            layout.background_style = BackgroundStyle::Image;
+            layout.background_image = path;
+        }));
+    });
+
+    let config_clone = config.clone();
+    app.on_change_overlay(move |select_index| {
+        let config_clone = config_clone.clone();
+        let _ = slint::spawn_local(async move {
+            let mut cfg = {
+                config_clone.lock().await.clone()
+            };
+            cfg.overlay_enabled = select_index > 0;
+            if let Some(corner) = (select_index as usize).checked_sub(1).and_then(|i| OVERLAY_CORNERS.get(i)){
+                cfg.overlay_corner = corner.to_string();
+            }
+            info!("修改文字信息，保存配置...");
+            cfg.current_wallpaper_date = "".to_string();
+            let _ = cfg.save_to_file().await;
+            *config_clone.lock().await = cfg.clone();
+
+            //立即更新
+            downloader::set_wallpaper_default(&mut cfg).await;
+        });
+    });
+
+    let config_clone = config.clone();
+    let app_clone = app.as_weak();
+    app.on_change_time_mode(move |select_index| {
+        let app = app_clone.unwrap();
+        let (pinned_time, days_ago) = match select_index{
+            1 => {
+                //默认使用一小时前的时间
+                let mut pinned_time = app.get_pinned_time().to_string();
+                if parse_pinned_time(&pinned_time).is_err(){
+                    pinned_time = (chrono::Utc::now() - chrono::Duration::hours(1)).format(PINNED_TIME_FORMAT).to_string();
+                    app.set_pinned_time(pinned_time.as_str().into());
+                }
+                (pinned_time, 0)
+            }
+            2 => (String::new(), app.get_days_ago().max(1) as u32),
+            _ => (String::new(), 0),
+        };
+        app.set_current_time_mode_index(select_index);
+        let _ = slint::spawn_local(save_wallpaper_time(config_clone.clone(), pinned_time, days_ago));
+    });
+
+    let config_clone = config.clone();
+    app.on_change_pinned_time(move |pinned_time| {
+        let pinned_time = pinned_time.trim().to_string();
+        if let Err(err) = parse_pinned_time(&pinned_time){
+            error!("{:?}", err);
+            return;
+        }
+        let _ = slint::spawn_local(save_wallpaper_time(config_clone.clone(), pinned_time, 0));
+    });
+
+    let config_clone = config.clone();
+    app.on_change_days_ago(move |days_ago| {
+        let _ = slint::spawn_local(save_wallpaper_time(config_clone.clone(), String::new(), days_ago.max(1) as u32));
+    });
+
+    let app_clone = app.as_weak();
+    app.on_refresh_history(move || {
+        let app_clone = app_clone.clone();
+        let _ = slint::spawn_local(async move {
+            load_history(app_clone, Config::load_or_default().await).await;
+        });
+    });
+
+    let app_clone = app.as_weak();
+    app.on_restore_history(move |path| {
+        let app_clone = app_clone.clone();
+        let _ = slint::spawn_local(async move {
+            if is_downlading(){
+                info!("恢复历史壁纸 正在下载中...");
+                return;
+            }
+            let cfg = Config::load_or_default().await;
+            let path = path.to_string();
+            let ret = spawn_blocking(move ||{
+                let mut cfg = cfg;
+                let history = WallpaperHistory::from_config(&cfg).unwrap_or_default();
+                let ret = history.find(&path).and_then(|entry| history.restore(&entry, &mut cfg));
+                (cfg, ret)
+            }).await;
+            let (mut cfg, ret) = ret;
+            let status = match ret{
+                Ok(()) => {
+                    let _ = cfg.save_to_file().await;
+                    format!("已设为壁纸:{}", cfg.current_wallpaper_date)
+                }
+                Err(err) => {
+                    error!("恢复历史壁纸失败:{:?}", err);
+                    format!("设置失败:{err}")
+                }
+            };
+            if let Some(app) = app_clone.upgrade(){
+                app.set_history_status(status.into());
+            }
+        });
+    });
+
+    app.on_open_history(move |path| {
+        open_file(&path);
+    });
+
+    let app_clone = app.as_weak();
+    app.on_export_history(move |path| {
+        let history = WallpaperHistory::default();
+        let status = match history.find(&path).and_then(|entry| history.export(&entry)){
+            Ok(path) => {
+                if let Some(dir) = path.parent(){
+                    open_file(&dir.to_string_lossy());
+                }
+                format!("已导出:{}", path.to_string_lossy())
+            }
+            Err(err) => {
+                error!("导出历史壁纸失败:{:?}", err);
+                format!("导出失败:{err}")
+            }
+        };
+        app_clone.unwrap().set_history_status(status.into());
+    });
+
     let app_clone = app.as_weak();
     app.on_change_startup(move |startup| {
         let is_registered = 
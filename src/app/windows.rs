@@ -97,8 +97,12 @@ pub fn run() -> Result<()> {
     env_logger::Builder::new().filter_level(LevelFilter::Info).init();
 
     let args: Vec<String> = std::env::args().collect();
-    for arg in &args[1..] {
+    for (i, arg) in args.iter().enumerate().skip(1) {
         let arg = arg.to_lowercase();
+        if arg == "/export" {
+            //导出延时动画
+            return crate::export::run_export_command(&args[i+1..]);
+        }
         if arg.starts_with("/c") {
             //打开设置页面
             info!("收到 /c参数，打开窗口");
@@ -229,4 +233,64 @@ pub fn set_wallpaper_from_path(image: &str) -> Result<()>{
 
 pub fn get_current_wallpaper() -> Result<String>{
     Ok(wallpaper::get().map_err(|err| anyhow!("{:?}", err))?)
+}
+
+use windows::Win32::{System::Com::{CoCreateInstance, CoInitializeEx, CoTaskMemFree, CLSCTX_ALL, COINIT_APARTMENTTHREADED}, UI::Shell::{DesktopWallpaper, IDesktopWallpaper}};
+
+fn desktop_wallpaper() -> Result<IDesktopWallpaper>{
+    unsafe{
+        // 当前线程已经初始化过COM时返回S_FALSE, 忽略
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        Ok(CoCreateInstance(&DesktopWallpaper, None::<&windows::core::IUnknown>, CLSCTX_ALL)?)
+    }
+}
+
+/// 通过IDesktopWallpaper获取所有已连接的显示器
+fn list_monitors() -> Result<Vec<super::Monitor>>{
+    let desktop = desktop_wallpaper()?;
+    let mut monitors = vec![];
+    unsafe{
+        for i in 0..desktop.GetMonitorDevicePathCount()?{
+            let path = desktop.GetMonitorDevicePathAt(i)?;
+            let id = path.to_string();
+            CoTaskMemFree(Some(path.0 as *const _));
+            let id = id?;
+            // 未连接的显示器获取位置失败
+            let Ok(rect) = desktop.GetMonitorRECT(&HSTRING::from(id.as_str())) else { continue };
+            monitors.push(super::Monitor{
+                id,
+                x: rect.left,
+                y: rect.top,
+                width: (rect.right - rect.left) as u32,
+                height: (rect.bottom - rect.top) as u32,
+                scale: 1.0,
+            });
+        }
+    }
+    Ok(monitors)
+}
+
+pub fn get_monitors() -> Vec<super::Monitor>{
+    match list_monitors(){
+        Ok(monitors) if !monitors.is_empty() => {
+            info!("显示器:{:?}", monitors);
+            monitors
+        }
+        Ok(_) => vec![super::Monitor::screen()],
+        Err(err) => {
+            info!("显示器列表获取失败:{:?}", err);
+            vec![super::Monitor::screen()]
+        }
+    }
+}
+
+/// 为每个显示器分别设置壁纸
+pub fn set_monitor_wallpapers(wallpapers: &[(super::Monitor, String)]) -> Result<()>{
+    let desktop = desktop_wallpaper()?;
+    for (monitor, image) in wallpapers{
+        unsafe{
+            desktop.SetWallpaper(&HSTRING::from(monitor.id.as_str()), &HSTRING::from(image.as_str()))?;
+        }
+    }
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,164 @@
+use anyhow::Result;
+use image::{buffer::ConvertBuffer, imageops, GenericImageView, Rgb, RgbImage, RgbaImage};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{downloader::fast_resize, layout::LayoutConfig, overlay::parse_color};
+
+/// 每百万像素的星星数量
+const STARS_PER_MEGAPIXEL: f64 = 600.0;
+/// 模糊背景在铺满屏幕的基础上再放大的倍数
+const BLUR_ENLARGE: f64 = 1.3;
+/// 模糊背景的亮度, 暗一些以突出地球
+const BLUR_BRIGHTNESS: f64 = 0.5;
+/// 模糊前先缩小的倍数, 在小图上模糊比较快
+const BLUR_DOWNSCALE: u32 = 16;
+
+/// 地球后面的背景
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackgroundStyle{
+    /// 纯色或从上到下的渐变
+    Color,
+    /// 固定种子生成的星空, 底色为纯色或渐变
+    Starfield,
+    /// 放大并模糊的卫星图片
+    Blur,
+    /// 自定义图片
+    Image,
+}
+
+/// 生成screen大小的背景, globe为下载的卫星图片, 用于模糊背景
+pub fn render(screen: (u32, u32), layout: &LayoutConfig, globe: &RgbaImage) -> RgbImage{
+    let (width, height) = screen;
+    match layout.background_style{
+        BackgroundStyle::Color => gradient(width, height, layout),
+        BackgroundStyle::Starfield => {
+            let mut paper = gradient(width, height, layout);
+            draw_stars(&mut paper, layout.starfield_seed);
+            paper
+        }
+        BackgroundStyle::Blur => blurred(globe, width, height),
+        BackgroundStyle::Image => match load_image(&layout.background_image, width, height){
+            Ok(paper) => paper,
+            Err(err) => {
+                error!("背景图片读取失败:{} {:?}", layout.background_image, err);
+                gradient(width, height, layout)
+            }
+        },
+    }
+}
+
+/// 纯色或从上到下渐变的背景
+fn gradient(width: u32, height: u32, layout: &LayoutConfig) -> RgbImage{
+    let top = parse_color(&layout.background).unwrap_or([0, 0, 0]);
+    if layout.background_gradient.trim().is_empty(){
+        return RgbImage::from_pixel(width, height, Rgb(top));
+    }
+    let bottom = parse_color(&layout.background_gradient).unwrap_or(top);
+    let mut paper = RgbImage::new(width, height);
+    for (y, row) in paper.enumerate_rows_mut(){
+        let t = y as f64 / (height.max(2) - 1) as f64;
+        let color = Rgb(std::array::from_fn(|c| (top[c] as f64 + (bottom[c] as f64 - top[c] as f64) * t).round() as u8));
+        for (_, _, pixel) in row{
+            *pixel = color;
+        }
+    }
+    paper
+}
+
+/// 固定种子的伪随机数(xorshift64), 每个平台生成的序列都相同
+struct Rng(u64);
+
+impl Rng{
+    fn new(seed: u32) -> Rng{
+        // 种子为0时xorshift会一直输出0
+        Rng((seed as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// 0~1之间的随机数
+    fn next(&mut self) -> f64{
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// 画星星, 位置按屏幕比例生成, 同一种子在不同分辨率下星空的样子相同
+fn draw_stars(paper: &mut RgbImage, seed: u32){
+    let (width, height) = paper.dimensions();
+    let mut rng = Rng::new(seed);
+    let count = (width as f64 * height as f64 / 1_000_000.0 * STARS_PER_MEGAPIXEL) as u32;
+    for _ in 0..count{
+        let x = rng.next() * width as f64;
+        let y = rng.next() * height as f64;
+        // 大部分星星很暗, 只有少数很亮
+        let brightness = 0.15 + 0.85 * rng.next().powi(6);
+        // 色温从偏蓝到偏黄
+        let tint = rng.next();
+        let color = [0.75 + 0.25 * tint, 0.85 + 0.1 * tint, 1.0 - 0.25 * tint].map(|c| c * brightness * 255.0);
+        let radius = if brightness > 0.6 { 1.2 } else { 0.6 };
+        draw_star(paper, x, y, radius, color);
+    }
+}
+
+/// 以(x, y)为中心画一个高斯光点, 亮度叠加到背景上
+fn draw_star(paper: &mut RgbImage, x: f64, y: f64, radius: f64, color: [f64; 3]){
+    let r = radius.ceil() as i64 + 1;
+    let (cx, cy) = (x.floor() as i64, y.floor() as i64);
+    for py in cy - r..=cy + r{
+        for px in cx - r..=cx + r{
+            if px < 0 || py < 0 || px >= paper.width() as i64 || py >= paper.height() as i64{
+                continue;
+            }
+            let (dx, dy) = (px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+            let a = (-(dx * dx + dy * dy) / (radius * radius)).exp();
+            if a < 0.02{
+                continue;
+            }
+            let pixel = paper.get_pixel_mut(px as u32, py as u32);
+            for c in 0..3{
+                pixel[c] = (pixel[c] as f64 + color[c] * a).round().min(255.0) as u8;
+            }
+        }
+    }
+}
+
+/// 原图中间和屏幕比例相同的部分 (x, y, w, h), enlarge越大取的部分越小
+fn cover_rect(src: (u32, u32), screen: (u32, u32), enlarge: f64) -> (u32, u32, u32, u32){
+    let aspect = screen.0 as f64 / screen.1 as f64;
+    let (sw, sh) = (src.0 as f64, src.1 as f64);
+    let (w, h) = if sw / sh > aspect { (sh * aspect, sh) } else { (sw, sw / aspect) };
+    let (w, h) = (((w / enlarge) as u32).clamp(1, src.0), ((h / enlarge) as u32).clamp(1, src.1));
+    ((src.0 - w) / 2, (src.1 - h) / 2, w, h)
+}
+
+/// 把卫星图片中间部分放大铺满屏幕, 模糊后调暗
+fn blurred(globe: &RgbaImage, width: u32, height: u32) -> RgbImage{
+    let (x, y, w, h) = cover_rect(globe.dimensions(), (width, height), BLUR_ENLARGE);
+    let small = imageops::thumbnail(&*globe.view(x, y, w, h), (width / BLUR_DOWNSCALE).max(1), (height / BLUR_DOWNSCALE).max(1));
+    // 透明部分(未下载的瓦片)按黑色处理
+    let small: RgbImage = small.convert();
+    let small = imageops::blur(&small, 2.0);
+    let mut paper = fast_resize(&small, width, height);
+    for pixel in paper.pixels_mut(){
+        for c in 0..3{
+            pixel[c] = (pixel[c] as f64 * BLUR_BRIGHTNESS).round() as u8;
+        }
+    }
+    paper
+}
+
+/// 读取自定义背景图片, 等比缩放铺满屏幕, 多出的部分裁掉
+fn load_image(path: &str, width: u32, height: u32) -> Result<RgbImage>{
+    let img = image::open(path)?.to_rgb8();
+    let (x, y, w, h) = cover_rect(img.dimensions(), (width, height), 1.0);
+    let part = img.view(x, y, w, h).to_image();
+    if part.dimensions() == (width, height){
+        return Ok(part);
+    }
+    Ok(fast_resize(&part, width, height))
+}
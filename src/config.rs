@@ -5,9 +5,10 @@ use log::info;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
-use crate::{app::get_config_dir, def::{APP_NAME_E, DEFAULT_DOWNLOAD_URL_FY4B, DEFAULT_DOWNLOAD_URL_H8, DEFAULT_SERVER_PORT}};
+use crate::{app::get_config_dir, def::{APP_NAME_E, DEFAULT_DOWNLOAD_URL_FY4B, DEFAULT_DOWNLOAD_URL_GOES, DEFAULT_DOWNLOAD_URL_H8, DEFAULT_DOWNLOAD_URL_METEOSAT, DEFAULT_SERVER_PORT}, layout::LayoutConfig};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     /// 壁纸更新时间间隔(分钟)
     pub update_interval: u32,
@@ -15,6 +16,84 @@ pub struct Config {
     /// 壁纸显示样式 1:整张 2:半张
     pub display_type: u32,
 
+    /// 显示的区域名字(east_asia、australia等), 为空时显示整个地球
+    pub region_name: String,
+
+    /// 自定义显示区域[西经度, 南纬度, 东经度, 北纬度], 优先于region_name, 经纬度无效时忽略
+    pub region_bbox: Option<[f64; 4]>,
+
+    /// 网格大小(每行瓦片数), 为空时根据屏幕分辨率自动选择
+    pub grid_size: Option<u32>,
+
+    /// 同时下载的瓦片数
+    pub download_concurrency: u32,
+
+    /// 瓦片下载失败后的重试次数
+    pub download_retries: u32,
+
+    /// 每张图片的下载时限(秒)
+    pub download_timeout: u32,
+
+    /// 瓦片缓存大小上限(MB), 0为不缓存
+    pub tile_cache_size_mb: u32,
+
+    /// 瓦片缓存保留天数, 0为不按时间清理(仍受大小上限限制)
+    pub tile_cache_days: u32,
+
+    /// 多显示器时壁纸横跨所有显示器, 否则每个显示器单独显示
+    pub span_monitors: bool,
+
+    /// 横跨模式下相邻显示器边框的宽度(像素)
+    pub bezel_gap: u32,
+
+    /// 在夜间部分叠加城市灯光
+    pub night_lights: bool,
+
+    /// 夜间灯光图(等经纬度投影), 为空时下载NASA Black Marble
+    pub night_lights_file: String,
+
+    /// 晨昏过渡带宽度(太阳在地平线以下的度数)
+    pub twilight_degrees: f64,
+
+    /// 在壁纸上显示文字信息
+    pub overlay_enabled: bool,
+
+    /// 文字位置 top_left、top_right、bottom_left、bottom_right
+    pub overlay_corner: String,
+
+    /// 文字颜色, 格式"#RRGGBB"
+    pub overlay_color: String,
+
+    /// 文字不透明度(%)
+    pub overlay_opacity: u32,
+
+    /// 字体大小(像素), 0为按屏幕高度自动选择
+    pub overlay_font_size: u32,
+
+    /// 字体文件路径, 为空时使用系统字体
+    pub overlay_font: String,
+
+    /// 显示卫星名字
+    pub overlay_show_satellite: bool,
+
+    /// 显示拍摄时间(本地时间和UTC)
+    pub overlay_show_time: bool,
+
+    /// 显示星下点经度, 并在地球上标出星下点
+    pub overlay_show_longitude: bool,
+
+    /// 自定义文字
+    pub overlay_caption: String,
+
+    /// 最多保存的历史壁纸数量, 0为不保存
+    pub history_max_count: u32,
+
+    /// 历史壁纸保留天数, 0为不按时间清理(仍受数量和大小上限限制)
+    pub history_days: u32,
+
+    /// 历史壁纸总大小上限(MB)
+    pub history_size_mb: u32,
+
     /// 服务器端口号
     pub server_port: u32,
 
@@ -22,10 +101,23 @@ pub struct Config {
     pub download_url_h8: String,
     /// 风云4号卫星图片下载地址
     pub download_url_fy4b: String,
+    /// GOES卫星图片下载地址
+    pub download_url_goes: String,
+    /// Meteosat卫星图片下载地址
+    pub download_url_meteosat: String,
 
-    /// 下载的卫星名字
+    /// 下载的卫星名字, composite为多星拼接
     pub satellite_name: String,
 
+    /// 多星拼接使用的卫星, 第一颗卫星决定图片时间
+    pub composite_satellites: Vec<String>,
+
+    /// 固定壁纸时间(UTC), 格式"2024-09-06 06:00", 为空时下载最新图片
+    pub pinned_time: String,
+
+    /// 显示N天前同一时刻的图片, 0为最新图片, pinned_time优先
+    pub days_ago: u32,
+
     /// 旧的桌面壁纸
     pub old_wallpaper: String,
 
@@ -40,6 +132,9 @@ pub struct Config {
 
     /// 配置文件路径
     pub config_path: String,
+
+    /// 壁纸布局, 放在最后以便写成[layout]表
+    pub layout: LayoutConfig,
 }
 
 impl Default for Config{
@@ -47,15 +142,47 @@ impl Default for Config{
         Self {
             update_interval: 10,
             display_type: 1,
+            region_name: String::new(),
+            region_bbox: None,
+            grid_size: None,
+            download_concurrency: 8,
+            download_retries: 3,
+            download_timeout: 180,
+            tile_cache_size_mb: 500,
+            tile_cache_days: 3,
+            span_monitors: false,
+            bezel_gap: 0,
+            night_lights: false,
+            night_lights_file: String::new(),
+            twilight_degrees: 12.0,
+            overlay_enabled: false,
+            overlay_corner: String::from("bottom_right"),
+            overlay_color: String::from("#FFFFFF"),
+            overlay_opacity: 80,
+            overlay_font_size: 0,
+            overlay_font: String::new(),
+            overlay_show_satellite: true,
+            overlay_show_time: true,
+            overlay_show_longitude: true,
+            overlay_caption: String::new(),
+            history_max_count: 200,
+            history_days: 30,
+            history_size_mb: 1024,
             server_port: DEFAULT_SERVER_PORT,
             download_url_h8: DEFAULT_DOWNLOAD_URL_H8.to_string(),
             download_url_fy4b: DEFAULT_DOWNLOAD_URL_FY4B.to_string(),
+            download_url_goes: DEFAULT_DOWNLOAD_URL_GOES.to_string(),
+            download_url_meteosat: DEFAULT_DOWNLOAD_URL_METEOSAT.to_string(),
             old_wallpaper: String::new(),
             current_wallpaper_date: String::new(),
             current_wallpaper_file: String::new(),
             config_path: String::new(),
             satellite_name: String::from("fy4b"),
-            last_download_timestamp: None
+            composite_satellites: ["fy4b", "h8", "goes_east", "goes_west", "meteosat"].iter().map(|s| s.to_string()).collect(),
+            pinned_time: String::new(),
+            days_ago: 0,
+            last_download_timestamp: None,
+            layout: LayoutConfig::default(),
         }
     }
 }
@@ -8,3 +8,7 @@ pub const DEFAULT_DOWNLOAD_URL_H8: &str = "https://himawari8.nict.go.jp/img/";
 // pub const DEFAULT_DOWNLOAD_URL_FY4A: &str = "http://rsapp.nsmc.org.cn/swapQuery/public/tileServer/getTile/fy-4a/reg_china/NatureColor/";
 // pub const DEFAULT_DOWNLOAD_URL_FY4A: &str = "http://rsapp.nsmc.org.cn/swapQuery/public/tileServer/getTile/fy-4a/full_disk/NatureColor_NoLit/";
 pub const DEFAULT_DOWNLOAD_URL_FY4B: &str = "http://rsapp.nsmc.org.cn/swapQuery/public/tileServer/getTile/fy-4b/full_disk/NatureColor_NoLit/";
+pub const DEFAULT_DOWNLOAD_URL_GOES: &str = "https://cdn.star.nesdis.noaa.gov/";
+pub const DEFAULT_DOWNLOAD_URL_METEOSAT: &str = "https://view.eumetsat.int/geoserver/wms";
+// NASA Black Marble 2016 夜间灯光图, 0.1度分辨率
+pub const DEFAULT_NIGHT_LIGHTS_URL: &str = "https://eoimages.gsfc.nasa.gov/images/imagerecords/144000/144898/BlackMarble_2016_01deg.jpg";
@@ -0,0 +1,139 @@
+use std::{fs, path::{Path, PathBuf}, time::{Duration, SystemTime}};
+use anyhow::Result;
+use image::RgbaImage;
+use log::{info, warn};
+use time::PrimitiveDateTime;
+
+use crate::{app::get_app_home_dir, config::Config, history::max_age_days};
+
+/// 瓦片缓存, 按 卫星/时间/网格_x_y.png 保存在app目录的tiles文件夹下
+#[derive(Clone, Debug)]
+pub struct TileCache{
+    dir: PathBuf,
+    /// 缓存总大小上限(字节)
+    max_bytes: u64,
+    /// 缓存保留时间, None为不按时间清理
+    max_age: Option<Duration>,
+}
+
+/// 一张瓦片的缓存键
+pub struct TileKey<'a>{
+    pub satellite: &'a str,
+    pub time: &'a PrimitiveDateTime,
+    pub d: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Default for TileCache{
+    fn default() -> Self {
+        Self {
+            dir: Path::new(&get_app_home_dir()).join("tiles"),
+            max_bytes: 500 * 1024 * 1024,
+            max_age: Some(Duration::from_secs(3 * 24 * 3600)),
+        }
+    }
+}
+
+impl TileCache{
+    /// 缓存大小设为0时不使用缓存, 保留天数设为0时不按时间清理
+    pub fn from_config(cfg: &Config) -> Option<Self>{
+        if cfg.tile_cache_size_mb == 0{
+            return None;
+        }
+        Some(Self {
+            max_bytes: cfg.tile_cache_size_mb as u64 * 1024 * 1024,
+            max_age: max_age_days(cfg.tile_cache_days),
+            ..Default::default()
+        })
+    }
+
+    fn path(&self, key: &TileKey) -> PathBuf{
+        let t = key.time;
+        self.dir
+            .join(key.satellite)
+            .join(format!("{}{:02}{:02}{:02}{:02}", t.year(), t.month() as u8, t.day(), t.hour(), t.minute()))
+            .join(format!("{}_{}_{}.png", key.d, key.x, key.y))
+    }
+
+    pub fn get(&self, key: &TileKey) -> Option<RgbaImage>{
+        let path = self.path(key);
+        if !path.exists(){
+            return None;
+        }
+        match image::open(&path){
+            Ok(img) => Some(img.to_rgba8()),
+            Err(err) => {
+                warn!("缓存读取失败:{:?} {:?}", path, err);
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, key: &TileKey, img: &RgbaImage) -> Result<()>{
+        let path = self.path(key);
+        if let Some(dir) = path.parent(){
+            fs::create_dir_all(dir)?;
+        }
+        img.save(&path)?;
+        Ok(())
+    }
+
+    /// 删除过期的瓦片, 总大小超出上限时从最旧的开始删除
+    pub fn evict(&self) -> Result<()>{
+        let mut files = vec![];
+        list_files(&self.dir, &mut files)?;
+        let now = SystemTime::now();
+        let mut total: u64 = 0;
+        let mut keep = vec![];
+        for (path, size, modified) in files{
+            if self.max_age.is_some_and(|max_age| now.duration_since(modified).unwrap_or_default() > max_age){
+                let _ = fs::remove_file(&path);
+            }else{
+                total += size;
+                keep.push((path, size, modified));
+            }
+        }
+        keep.sort_by_key(|(_, _, modified)| *modified);
+        for (path, size, _) in keep{
+            if total <= self.max_bytes{
+                break;
+            }
+            let _ = fs::remove_file(&path);
+            total -= size;
+        }
+        remove_empty_dirs(&self.dir);
+        info!("瓦片缓存清理完成, 当前大小:{}KB", total / 1024);
+        Ok(())
+    }
+}
+
+fn list_files(dir: &Path, files: &mut Vec<(PathBuf, u64, SystemTime)>) -> Result<()>{
+    if !dir.exists(){
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)?{
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir(){
+            list_files(&entry.path(), files)?;
+        }else{
+            files.push((entry.path(), meta.len(), meta.modified()?));
+        }
+    }
+    Ok(())
+}
+
+fn remove_empty_dirs(dir: &Path){
+    if let Ok(entries) = fs::read_dir(dir){
+        for entry in entries.flatten(){
+            let path = entry.path();
+            if path.is_dir(){
+                remove_empty_dirs(&path);
+                // 非空文件夹删除会失败
+                let _ = fs::remove_dir(&path);
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::Instant;
+use anyhow::{anyhow, Result};
+use image::{Rgba, RgbaImage};
+use log::{error, info, warn};
+use time::PrimitiveDateTime;
+
+use crate::{config::Config, downloader::{fetcher::FetchOptions, format_time_str, source::{self, get_source, SatelliteSource, Visible}}, projection::{lonlat_to_pixel, sample, view_angle_cos, Disk}};
+
+/// 多星拼接模式在Config.satellite_name中的名字
+pub const NAME: &str = "composite";
+
+/// 离星下点的角度超过该值的像素不参与拼接(度), 边缘变形太大
+const MAX_VIEW_ANGLE: f64 = 75.0;
+
+/// 全球图宽度为d*1024, 根据需要的宽度选择d
+pub fn grid_size(map_width: f64) -> u32{
+    ((map_width / 1024.0).ceil() as u32).clamp(1, 8)
+}
+
+/// 下载多颗卫星同一时刻的全圆盘图，投影到等经纬度地图上拼接成全球图
+pub fn download_lastest<C:Fn(u32, u32) + 'static>(cfg: &Config, d:u32, callback:C ) -> Result<Option<(String, PrimitiveDateTime, RgbaImage)>>{
+    let satellites: Vec<&dyn SatelliteSource> = cfg.composite_satellites.iter().map(|name| get_source(name)).collect();
+    if satellites.is_empty(){
+        return Err(anyhow!("没有配置拼接的卫星"));
+    }
+    // 各卫星的间隔不同(10分钟/15分钟), 取半点对齐的时间
+    let time = source::wallpaper_time(satellites[0], cfg)?;
+    let time = time.replace_minute((time.minute()/30)*30)?;
+    let timestr = format_time_str(NAME, d, time.year(), time.month() as u8, time.day(), time.hour(), time.minute());
+    info!("时间:{}", timestr);
+    if cfg.current_wallpaper_date == timestr{
+        warn!("壁纸无需重复下载");
+        return Ok(None);
+    }
+
+    let total = satellites.len() as u32;
+    let mut disks = vec![];
+    for (i, satellite) in satellites.into_iter().enumerate(){
+        match download_disk(satellite, cfg, d, time){
+            Ok(img) => disks.push((satellite.disk(), img)),
+            Err(err) => error!("{}下载失败, 不参与拼接:{:?}", satellite.name(), err),
+        }
+        callback(i as u32 + 1, total);
+    }
+    if disks.is_empty(){
+        return Err(anyhow!("图片下载失败."));
+    }
+
+    let t = Instant::now();
+    let img = blend(&disks, d * 1024);
+    info!("全球图拼接完成 {}x{}. 耗时:{}ms", img.width(), img.height(), t.elapsed().as_millis());
+    Ok(Some((timestr, time, img)))
+}
+
+fn download_disk(satellite: &dyn SatelliteSource, cfg: &Config, d: u32, time: PrimitiveDateTime) -> Result<RgbaImage>{
+    let name = satellite.name();
+    let options = FetchOptions::from_config(cfg);
+    let part = source::download_part(satellite, satellite.base_url(cfg), satellite.grid_size(d), time, Visible::ALL, &options, move |i, t|{
+        info!("{name} 正在下载: {i}/{t}");
+    })?;
+    Ok(part.image)
+}
+
+/// 把多张全圆盘图投影到width x width/2的等经纬度地图上, 重叠部分按离星下点的距离加权混合
+fn blend(disks: &[(Disk, RgbaImage)], width: u32) -> RgbaImage{
+    let height = width / 2;
+    let mut map = RgbaImage::new(width, height);
+    let min_cos = MAX_VIEW_ANGLE.to_radians().cos();
+    for y in 0..height{
+        let lat = 90.0 - (y as f64 + 0.5) * 180.0 / height as f64;
+        for x in 0..width{
+            let lon = (x as f64 + 0.5) * 360.0 / width as f64 - 180.0;
+            let mut sum = [0.0; 3];
+            let mut weight_sum = 0.0;
+            for (projection, disk) in disks{
+                // 离星下点的角度越小权重越大
+                let cos_view = view_angle_cos(lon, lat, projection.sub_lon);
+                if cos_view <= min_cos{
+                    continue;
+                }
+                if let Some((px, py)) = lonlat_to_pixel(lon, lat, *projection, disk.width(), disk.height()){
+                    let weight = cos_view - min_cos;
+                    let pixel = sample(disk, px, py);
+                    for c in 0..3{
+                        sum[c] += pixel[c] * weight;
+                    }
+                    weight_sum += weight;
+                }
+            }
+            if weight_sum > 0.0{
+                map.put_pixel(x, y, Rgba([
+                    (sum[0] / weight_sum) as u8,
+                    (sum[1] / weight_sum) as u8,
+                    (sum[2] / weight_sum) as u8,
+                    255,
+                ]));
+            }
+        }
+    }
+    map
+}
@@ -0,0 +1,118 @@
+use std::{collections::VecDeque, sync::{mpsc, Arc, Mutex}, time::{Duration, Instant}};
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use log::{error, info, warn};
+
+use crate::{config::Config, downloader::{cache::TileCache, download_image}};
+
+/// 瓦片下载参数
+#[derive(Clone, Debug)]
+pub struct FetchOptions{
+    /// 同时下载的瓦片数
+    pub concurrency: usize,
+    /// 每张瓦片失败后的重试次数
+    pub retries: u32,
+    /// 第一次重试前的等待时间, 之后每次翻倍
+    pub backoff: Duration,
+    /// 整张图的下载时限
+    pub deadline: Duration,
+    /// 瓦片缓存, 为空时不使用缓存
+    pub cache: Option<TileCache>,
+}
+
+impl Default for FetchOptions{
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            deadline: Duration::from_secs(180),
+            cache: Some(TileCache::default()),
+        }
+    }
+}
+
+impl FetchOptions{
+    pub fn from_config(cfg: &Config) -> Self{
+        Self {
+            concurrency: cfg.download_concurrency.max(1) as usize,
+            retries: cfg.download_retries,
+            deadline: Duration::from_secs(cfg.download_timeout as u64),
+            cache: TileCache::from_config(cfg),
+            ..Default::default()
+        }
+    }
+}
+
+/// 用固定数量的线程下载所有瓦片, 结果和urls的顺序一致
+///
+/// 任意一张瓦片重试后仍然失败, 或超过时限时返回错误
+pub fn fetch_tiles<C>(urls: Vec<String>, options: &FetchOptions, callback: C) -> Result<Vec<RgbaImage>>
+where
+    C: Fn(u32, u32),
+{
+    let total = urls.len();
+    let deadline = Instant::now() + options.deadline;
+    let jobs = Arc::new(Mutex::new(urls.into_iter().enumerate().collect::<VecDeque<_>>()));
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..options.concurrency.min(total){
+        let jobs = jobs.clone();
+        let tx = tx.clone();
+        let options = options.clone();
+        std::thread::spawn(move ||{
+            loop{
+                let job = jobs.lock().ok().and_then(|mut jobs| jobs.pop_front());
+                let Some((i, url)) = job else { break };
+                let ret = fetch_with_retry(&url, &options, deadline);
+                if tx.send((i, ret)).is_err(){
+                    //已经放弃下载
+                    break;
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    let mut images = vec![None; total];
+    for count in 1..=total{
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let ret = match rx.recv_timeout(timeout){
+            Ok((i, Ok(img))) => Ok((i, img)),
+            Ok((i, Err(err))) => Err(anyhow!("{i}号图片下载失败! {:?}", err)),
+            Err(err) => Err(anyhow!("图片下载超时! {:?}", err)),
+        };
+        match ret{
+            Ok((i, img)) => images[i] = Some(img),
+            Err(err) => {
+                error!("{:?}", err);
+                //丢弃剩余的任务
+                if let Ok(mut jobs) = jobs.lock(){
+                    jobs.clear();
+                }
+                return Err(err);
+            }
+        }
+        callback(count as u32, total as u32);
+    }
+    info!("瓦片下载完成 共{total}张");
+    Ok(images.into_iter().flatten().collect())
+}
+
+fn fetch_with_retry(url: &str, options: &FetchOptions, deadline: Instant) -> Result<RgbaImage>{
+    let mut backoff = options.backoff;
+    let mut attempt = 0;
+    loop{
+        match download_image(url){
+            Ok(img) => return Ok(img),
+            Err(err) => {
+                if attempt >= options.retries || Instant::now() + backoff >= deadline{
+                    return Err(err);
+                }
+                attempt += 1;
+                warn!("瓦片下载失败, {}ms后第{attempt}次重试: {url} {:?}", backoff.as_millis(), err);
+                std::thread::sleep(backoff);
+                backoff *= 2;
+            }
+        }
+    }
+}
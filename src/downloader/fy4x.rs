@@ -1,15 +1,57 @@
-use std::{ops::Sub, time::{Duration, Instant}};
-use anyhow::{anyhow, Result};
-use image::{GenericImage, ImageBuffer, Rgba, RgbaImage};
-use log::{error, info, warn};
-use time::{OffsetDateTime, Date};
+use std::{ops::Sub, time::Duration};
+use anyhow::Result;
+use image::RgbaImage;
+use time::{OffsetDateTime, Date, PrimitiveDateTime};
 
-use crate::{config::Config, downloader::{download_image, format_time_str}};
+use crate::{config::Config, downloader::source::{self, utc_time, SatelliteSource}};
 
 //http://rsapp.nsmc.org.cn/geofy/
 
+/// 风云4号B星
+pub struct Fy4b;
 
-/// 下载4x4、2x2的图
+impl SatelliteSource for Fy4b{
+    fn name(&self) -> &'static str{
+        "fy4b"
+    }
+
+    fn longitude(&self) -> f64{
+        105.0
+    }
+
+    fn base_url<'a>(&self, cfg: &'a Config) -> &'a str{
+        &cfg.download_url_fy4b
+    }
+
+    fn grid_sizes(&self) -> &'static [u32]{
+        &[2, 4, 8]
+    }
+
+    fn tile_size(&self) -> u32{
+        256
+    }
+
+    fn tile_url(&self, base_url: &str, time: &PrimitiveDateTime, d: u32, x: u32, y: u32) -> String{
+        // 瓦片路径为 级别/行/列, 级别n对应2^n x 2^n张图
+        format_url(base_url, time.year(), time.month() as u8, time.day(), time.hour(), time.minute(), d.trailing_zeros(), y, x)
+    }
+
+    fn interval(&self) -> u32{
+        15
+    }
+
+    /// 从当前时间以15分钟倒推
+    fn timestamps(&self, now: OffsetDateTime) -> Vec<PrimitiveDateTime>{
+        let hour = Date::from_calendar_date(now.year(), now.month(), now.day())
+            .and_then(|today| today.with_hms(now.hour(), (now.minute()/15)*15, 0));
+        match hour{
+            Ok(hour) => (1..=4).map(|i| hour.sub(Duration::from_secs(60*15*i))).collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+/// 下载d x d的图, d可以是2、4、8
 pub fn download<C>(
     url: &str,
     d: u32,
@@ -23,65 +65,10 @@ pub fn download<C>(
 where
     C: Fn(u32, u32) + 'static,
 {
-    let total = d*d;
-    info!("开始下载图片 共{total}张...");
-    let t = Instant::now();
-    let (tx, rx) = std::sync::mpsc::channel();
-    let mut count = 0;
-    for y in 0..d{
-        for x in 0..d{
-            let url1 = url.to_string();
-            let tx1 = tx.clone();
-            std::thread::spawn(move ||{
-                let ret = download_image(&format_url(&url1, year, month, day, hour, minute, d/2, x, y));
-                let _ = tx1.send((count, ret));
-            });
-            count += 1;
-        }
-    }
-
-    let mut images = vec![None; total as usize];
-
-    let mut count = 0;
-    for _ in 0..total{
-        let r = rx.recv();
-        if r.is_err(){
-            error!("图片下载失败:{:?}", r.err());
-            break;
-        }
-        let (i, img) = r.unwrap();
-        if img.is_err(){
-            error!("图片下载失败:{:?}", img.err());
-            break;
-        }
-        images[i as usize] = Some(img?);
-        count += 1;
-        callback(count, total);
-    }
-
-    for (i, img) in images.iter().enumerate(){
-        if img.is_none(){
-            return Err(anyhow!("{i}号图片下载失败!"));
-        }
-    }
-    
-    let mut images:Vec<ImageBuffer<Rgba<u8>, Vec<u8>>> = images.into_iter().map(|v| v.unwrap()).collect();
-
-    info!("图片下载完成 共{}张. 耗时:{}ms", images.len(), t.elapsed().as_millis());
-    let t = Instant::now();
-    let (width, height) = (images[0].width(), images[0].height());
-    let mut big_img = RgbaImage::new(width*d, height*d);
-    for x in 0..d{
-        for y in 0..d{
-            let img = images.remove(0);
-            big_img.sub_image(x*width, y*height, img.width(), img.height()).copy_from(&img, 0, 0)?;
-        }
-    }
-    info!("图片合并完成 {}x{}. 耗时:{}ms", big_img.width(), big_img.height(), t.elapsed().as_millis());
-    Ok(big_img)
+    source::download(&Fy4b, url, d, utc_time(year, month, day, hour, minute)?, callback)
 }
 
-// d 1代表4张图, 2代表16张图
+// d 1代表4张图, 2代表16张图, 3代表64张图
 pub fn format_url(
     url: &str,
     year: i32,
@@ -100,35 +87,6 @@ pub fn format_url(
 }
 
 /// 下载最新图片, 20分钟之前
-pub fn download_lastest<C:Fn(u32, u32) + 'static>(cfg: &Config, d:u32, callback:C ) -> Result<Option<(String, RgbaImage)>>{
-    
-    // 从当前时间以15分钟倒推，查询最后可下载的图片
-    let now = OffsetDateTime::now_utc();
-    let today = Date::from_calendar_date(now.year(), now.month(), now.day())?;
-    let hour = today.with_hms(now.hour(), (now.minute()/15)*15, 0)?;
-
-    let mut time = hour.sub(Duration::from_secs(60*15));
-    // let mut time = hour.sub();
-
-    let mut try_times = 0;
-    while try_times < 4{
-        //尝试下载最新一张图片, 递减15分钟
-        let ret = download_image(&format_url(&cfg.download_url_fy4b, time.year(), time.month() as u8, time.day(), time.hour(), time.minute(), 1, 0, 0));
-        if ret.is_err(){
-            log::error!("download_image失败: {:?}", ret.err());
-            info!("卫星图片不存在，尝试下载更早的图片.");
-            time = time.sub(Duration::from_secs(60*15));
-            try_times += 1;
-        }else{
-            break;
-        }
-    }
-    let timestr = format_time_str(&cfg.satellite_name, d, time.year(), time.month() as u8, time.day(), time.hour(), time.minute());
-    info!("时间:{}", timestr);
-    if cfg.current_wallpaper_date == timestr{
-        warn!("壁纸无需重复下载");
-        return Ok(None);
-    }
-    let img = download(&cfg.download_url_fy4b, d, time.year(), time.month() as u8, time.day(), time.hour(), time.minute(), callback)?;
-    Ok(Some((timestr, img)))
-}
\ No newline at end of file
+pub fn download_lastest<C:Fn(u32, u32) + 'static>(cfg: &Config, d:u32, callback:C ) -> Result<Option<(String, PrimitiveDateTime, RgbaImage)>>{
+    source::download_lastest(&Fy4b, cfg, d, callback)
+}
@@ -0,0 +1,89 @@
+use std::{ops::Sub, time::Duration};
+use anyhow::Result;
+use image::RgbaImage;
+use time::{OffsetDateTime, Date, PrimitiveDateTime};
+
+use crate::{config::Config, downloader::source::{self, SatelliteSource}};
+
+// https://www.star.nesdis.noaa.gov/GOES/fulldisk.php
+
+/// NOAA GOES卫星 GeoColor全圆盘图
+pub struct Goes{
+    name: &'static str,
+    longitude: f64,
+    /// CDN目录名
+    satellite: &'static str,
+}
+
+/// GOES东星(西经75.2°), 2025年4月起由GOES-19接替GOES-16
+pub static GOES_EAST: Goes = Goes{ name: "goes_east", longitude: -75.2, satellite: "GOES19" };
+
+/// GOES西星(西经137.2°)
+pub static GOES_WEST: Goes = Goes{ name: "goes_west", longitude: -137.2, satellite: "GOES18" };
+
+impl SatelliteSource for Goes{
+    fn name(&self) -> &'static str{
+        self.name
+    }
+
+    fn longitude(&self) -> f64{
+        self.longitude
+    }
+
+    fn base_url<'a>(&self, cfg: &'a Config) -> &'a str{
+        &cfg.download_url_goes
+    }
+
+    /// CDN上只有整张图, 不分瓦片
+    fn grid_sizes(&self) -> &'static [u32]{
+        &[1]
+    }
+
+    fn tile_size(&self) -> u32{
+        1808
+    }
+
+    fn tile_url(&self, base_url: &str, time: &PrimitiveDateTime, _d: u32, _x: u32, _y: u32) -> String{
+        format_url(base_url, self.satellite, time.year(), time.ordinal(), time.hour(), time.minute())
+    }
+
+    fn interval(&self) -> u32{
+        10
+    }
+
+    /// 全圆盘图每10分钟一张, 发布有20分钟左右的延迟, 从20分钟前以10分钟倒推
+    fn timestamps(&self, now: OffsetDateTime) -> Vec<PrimitiveDateTime>{
+        let hour = Date::from_calendar_date(now.year(), now.month(), now.day())
+            .and_then(|today| today.with_hms(now.hour(), (now.minute()/10)*10, 0));
+        match hour{
+            Ok(hour) => (2..=7).map(|i| hour.sub(Duration::from_secs(60*10*i))).collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+/// 下载一张1808x1808的全圆盘图, time为UTC时间
+pub fn download<C>(goes: &Goes, url: &str, time: PrimitiveDateTime, callback: C) -> Result<RgbaImage>
+where
+    C: Fn(u32, u32) + 'static,
+{
+    source::download(goes, url, 1, time, callback)
+}
+
+pub fn format_url(
+    url: &str,
+    satellite: &str,
+    year: i32,
+    day_of_year: u16,
+    hour: u8,
+    minute: u8,
+) -> String {
+    format!("{}{}/ABI/FD/GEOCOLOR/{}{:03}{:02}{:02}_{}-ABI-FD-GEOCOLOR-1808x1808.jpg", url, satellite, year, day_of_year, hour, minute, satellite)
+    // GOES19/ABI/FD/GEOCOLOR/20251201200_GOES19-ABI-FD-GEOCOLOR-1808x1808.jpg 2025年第120天12点00分
+}
+
+/// 下载最新图片, 根据cfg.satellite_name选择东星或西星
+pub fn download_lastest<C:Fn(u32, u32) + 'static>(cfg: &Config, d:u32, callback:C ) -> Result<Option<(String, PrimitiveDateTime, RgbaImage)>>{
+    let goes = if cfg.satellite_name == GOES_WEST.name{ &GOES_WEST }else{ &GOES_EAST };
+    source::download_lastest(goes, cfg, goes.grid_size(d), callback)
+}
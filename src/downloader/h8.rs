@@ -1,12 +1,87 @@
-use std::time::Instant;
+use std::time::Duration;
 use anyhow::{anyhow, Result};
-use image::{GenericImage, ImageBuffer, Rgba, RgbaImage};
-use log::{error, info, warn};
-use time::OffsetDateTime;
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use image::RgbaImage;
+use log::{info, warn};
+use time::{Date, OffsetDateTime, PrimitiveDateTime};
 
-use crate::{config::Config, downloader::{download_image, format_time_str}};
+use crate::{config::Config, downloader::source::{self, utc_time, SatelliteSource}};
 
-/// 下载4x4、2x2的图，最终大小: 1100x1100 、2200x2200
+/// 向日葵8号
+pub struct H8;
+
+impl SatelliteSource for H8{
+    fn name(&self) -> &'static str{
+        "h8"
+    }
+
+    /// 向日葵9号已接替8号, 位置不变
+    fn longitude(&self) -> f64{
+        140.7
+    }
+
+    fn base_url<'a>(&self, cfg: &'a Config) -> &'a str{
+        &cfg.download_url_h8
+    }
+
+    fn grid_sizes(&self) -> &'static [u32]{
+        &[1, 2, 4, 8, 16, 20]
+    }
+
+    fn tile_size(&self) -> u32{
+        550
+    }
+
+    fn tile_url(&self, base_url: &str, time: &PrimitiveDateTime, d: u32, x: u32, y: u32) -> String{
+        format_url(base_url, time.year(), time.month() as u8, time.day(), time.hour(), time.minute(), d, x, y)
+    }
+
+    fn interval(&self) -> u32{
+        10
+    }
+
+    /// 每10分钟一张图, 从10分钟前倒推1小时
+    fn timestamps(&self, now: OffsetDateTime) -> Vec<PrimitiveDateTime>{
+        let slot = Date::from_calendar_date(now.year(), now.month(), now.day())
+            .and_then(|today| today.with_hms(now.hour(), (now.minute()/10)*10, 0));
+        match slot{
+            Ok(slot) => (1..=6).map(|i| slot - Duration::from_secs(60*10*i)).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    fn latest_time(&self, cfg: &Config) -> Result<PrimitiveDateTime>{
+        latest_time(cfg, OffsetDateTime::now_utc())
+    }
+}
+
+/// 优先读取latest.json, 失败时从now开始以10分钟倒推检查图片是否存在
+fn latest_time(cfg: &Config, now: OffsetDateTime) -> Result<PrimitiveDateTime>{
+    match query_latest_json(&cfg.download_url_h8){
+        Ok(time) => {
+            info!("latest.json 最新时间:{time}");
+            Ok(time)
+        }
+        Err(err) => {
+            warn!("latest.json读取失败:{:?}", err);
+            H8.probe_latest_time(cfg, now)
+        }
+    }
+}
+
+/// 读取 D531106/latest.json: {"date":"2024-10-29 12:10:00","file":"PI_H09_20241029_1210_TRC_FLDK_R10_PGPFD.png"}
+pub fn query_latest_json(url: &str) -> Result<PrimitiveDateTime>{
+    let response = minreq::get(format!("{url}D531106/latest.json")).with_timeout(10).send()?;
+    if response.status_code != 200{
+        return Err(anyhow!("latest.json 状态码:{}", response.status_code));
+    }
+    let latest: serde_json::Value = serde_json::from_slice(response.as_bytes())?;
+    let date = latest["date"].as_str().ok_or(anyhow!("latest.json中没有date"))?;
+    let date = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")?;
+    utc_time(date.year(), date.month() as u8, date.day() as u8, date.hour() as u8, date.minute() as u8)
+}
+
+/// 下载d x d的图，最终大小: 550*d x 550*d, d可以是1、2、4、8、16、20
 pub fn download<C>(
     url: &str,
     d: u32,
@@ -14,106 +89,136 @@ pub fn download<C>(
     month: u8,
     day: u8,
     hour: u8,
-    ten_minute: u8,
+    minute: u8,
     callback: C,
 ) -> Result<RgbaImage>
 where
     C: Fn(u32, u32) + 'static,
 {
-    /*
-    4d的图排列如下:
-    00,10,20,30
-    01,11,21,31
-    02,12,22,32
-    03,13,23,33
-    2d的图排列如下:
-    00,10
-    01,11
-     */
-    let mut count = 0;
-    let total = d*d;
-    let t = Instant::now();
-    info!("开始下载图片 共{total}张...");
-    let (tx, rx) = std::sync::mpsc::channel();
-
-    for y in 0..d{
-        for x in 0..d{
-            let url1 = url.to_string();
-            let tx1 = tx.clone();
-            std::thread::spawn(move ||{
-                let ret = download_image(&format_url(&url1, year, month, day, hour, ten_minute / 10, d, x, y));
-                let _ = tx1.send((count, ret));
-            });
-            count += 1;
-        }
-    }
-
-    let mut images = vec![None; total as usize];
-
-    let mut count = 0;
-    for _ in 0..total{
-        let r = rx.recv();
-        if r.is_err(){
-            error!("图片下载超时:{:?}", r.err());
-            break;
-        }
-        let (i, img) = r.unwrap();
-        if img.is_err(){
-            error!("图片下载失败:{:?}", img.err());
-            break;
-        }
-        images[i as usize] = Some(img?);
-        count += 1;
-        callback(count, total);
-    }
-    
-    for (i, img) in images.iter().enumerate(){
-        if img.is_none(){
-            return Err(anyhow!("{i}号图片下载失败!"));
-        }
-    }
-    
-    let mut images:Vec<ImageBuffer<Rgba<u8>, Vec<u8>>> = images.into_iter().map(|v| v.unwrap()).collect();
-
-    info!("图片下载完成 共{}张. 耗时:{}ms", images.len(), t.elapsed().as_millis());
-
-    let (width, height) = (images[0].width(), images[0].height());
-    let mut big_img = RgbaImage::new(width*d, height*d);
-    for y in 0..d{
-        for x in 0..d{
-            let img = images.remove(0);
-            big_img.sub_image(x*width, y*height, img.width(), img.height()).copy_from(&img, 0, 0)?;
-        }
-    }
-    Ok(big_img)
+    source::download(&H8, url, d, utc_time(year, month, day, hour, minute)?, callback)
 }
 
-fn format_url(
+pub fn format_url(
     url: &str,
     year: i32,
     month: u8,
     day: u8,
     hour: u8,
-    ten_minute: u8,
+    minute: u8,
     d: u32,
     x: u32,
     y: u32,
 ) -> String {
-    format!("{}D531106/{}d/550/{}/{:02}/{:02}/{:02}{}000_{}_{}.png", url, d, year, month, day, hour, ten_minute/10, x, y)
+    format!("{}D531106/{}d/550/{}/{:02}/{:02}/{:02}{}000_{}_{}.png", url, d, year, month, day, hour, minute/10, x, y)
+}
+
+/// 下载最新图片
+pub fn download_lastest<C:Fn(u32, u32) + 'static>(cfg: &Config, d:u32, callback:C ) -> Result<Option<(String, PrimitiveDateTime, RgbaImage)>>{
+    source::download_lastest(&H8, cfg, d, callback)
 }
 
-/// 下载最新图片, 20分钟之前
-pub fn download_lastest<C:Fn(u32, u32) + 'static>(cfg: &Config, d:u32, callback:C ) -> Result<Option<(String, RgbaImage)>>{
-    let mut timestamp = OffsetDateTime::now_utc().unix_timestamp();
-    //减去20分钟
-    timestamp -= 20 * 60 * 1000;
-    let utc = OffsetDateTime::from_unix_timestamp(timestamp)?;
-    let timestr = format_time_str(&cfg.satellite_name, d, utc.year(), utc.month() as u8, utc.day(), utc.hour(), utc.minute());
-    info!("时间:{}", timestr);
-    if cfg.current_wallpaper_date == timestr{
-        warn!("壁纸无需重复下载");
-        return Ok(None);
-    }
-    let img = download(&cfg.download_url_h8, d, utc.year(), utc.month() as u8, utc.day(), utc.hour(), utc.minute(), callback)?;
-    Ok(Some((timestr, img)))
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests{
+    use std::{io::{BufRead, BufReader, Cursor, Write}, net::TcpListener, thread};
+    use image::{ImageFormat, Rgba};
+    use time::macros::datetime;
+    use super::*;
+
+    /// 在本地启动一个HTTP服务, route根据请求路径返回(状态码, 内容), 返回服务地址
+    fn serve<F>(route: F) -> String
+    where
+        F: Fn(&str) -> (u16, Vec<u8>) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move ||{
+            for stream in listener.incoming(){
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                let _ = reader.read_line(&mut line);
+                let path = line.split_whitespace().nth(1).unwrap_or("/").to_string();
+                // 跳过请求头
+                loop{
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap_or(0) <= 2{
+                        break;
+                    }
+                }
+                let (status, body) = route(&path);
+                let head = format!("HTTP/1.1 {status} OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+        format!("http://127.0.0.1:{port}/")
+    }
+
+    fn png(img: RgbaImage) -> Vec<u8>{
+        let mut buf = Cursor::new(vec![]);
+        img.write_to(&mut buf, ImageFormat::Png).unwrap();
+        buf.into_inner()
+    }
+
+    fn config(url: String) -> Config{
+        Config{ download_url_h8: url, ..Config::default() }
+    }
+
+    /// 12:10的瓦片为"No Image"占位图, 12:00的瓦片正常, 其它时间不存在
+    fn tiles(path: &str) -> (u16, Vec<u8>){
+        if path.ends_with("/2024/10/29/121000_0_0.png"){
+            (200, png(RgbaImage::from_pixel(550, 550, Rgba([128, 128, 128, 255]))))
+        }else if path.ends_with("/2024/10/29/120000_0_0.png"){
+            (200, png(RgbaImage::from_fn(550, 550, |x, y| Rgba([(x % 200) as u8, (y % 200) as u8, 100, 255]))))
+        }else{
+            (404, b"not found".to_vec())
+        }
+    }
+
+    const NOW: OffsetDateTime = datetime!(2024-10-29 12:25 UTC);
+
+    #[test]
+    fn parse_latest_json(){
+        let url = serve(|path|{
+            if path == "/D531106/latest.json"{
+                (200, br#"{"date":"2024-10-29 12:10:00","file":"PI_H09_20241029_1210_TRC_FLDK_R10_PGPFD.png"}"#.to_vec())
+            }else{
+                (404, vec![])
+            }
+        });
+        assert_eq!(query_latest_json(&url).unwrap(), datetime!(2024-10-29 12:10));
+        assert_eq!(latest_time(&config(url), NOW).unwrap(), datetime!(2024-10-29 12:10));
+    }
+
+    #[test]
+    fn missing_latest_json_falls_back_to_probe(){
+        let url = serve(tiles);
+        assert!(query_latest_json(&url).is_err());
+        // 12:10为占位图被跳过
+        assert_eq!(latest_time(&config(url), NOW).unwrap(), datetime!(2024-10-29 12:00));
+    }
+
+    #[test]
+    fn bad_latest_json_falls_back_to_probe(){
+        let url = serve(|path|{
+            if path == "/D531106/latest.json"{
+                (200, b"<html>busy</html>".to_vec())
+            }else{
+                tiles(path)
+            }
+        });
+        assert!(query_latest_json(&url).is_err());
+        assert_eq!(latest_time(&config(url), NOW).unwrap(), datetime!(2024-10-29 12:00));
+    }
+
+    #[test]
+    fn pinned_time_starts_at_its_own_slot(){
+        assert_eq!(H8.pinned_timestamps(datetime!(2024-10-29 12:04:30))[0], datetime!(2024-10-29 12:00));
+        let url = serve(tiles);
+        let cfg = Config{ pinned_time: String::from("2024-10-29 12:04"), ..config(url.clone()) };
+        assert_eq!(source::wallpaper_time(&H8, &cfg).unwrap(), datetime!(2024-10-29 12:00));
+        // 12:10为占位图, 往前找到12:00
+        let cfg = Config{ pinned_time: String::from("2024-10-29 12:19"), ..config(url) };
+        assert_eq!(source::wallpaper_time(&H8, &cfg).unwrap(), datetime!(2024-10-29 12:00));
+    }
+}
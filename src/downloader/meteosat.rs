@@ -0,0 +1,132 @@
+use std::{ops::Sub, time::Duration};
+use anyhow::Result;
+use image::RgbaImage;
+use time::{OffsetDateTime, Date, PrimitiveDateTime};
+
+use crate::{config::Config, downloader::source::{self, SatelliteSource}, projection::Projection};
+
+// https://view.eumetsat.int/productviewer
+// EUMETView WMS服务, 使用正射投影(AUTO:42003)以星下点为中心生成地球圆盘, 按bbox切分瓦片
+// 正射投影和其它卫星的地球静止轨道投影不同, 裁剪区域、夜间灯光和拼接时按Projection::Orthographic计算
+
+/// 地球半径(米), 正射投影下圆盘的半径
+const EARTH_RADIUS: f64 = 6378137.0;
+
+/// EUMETSAT Meteosat卫星 增强自然色全圆盘图
+pub struct Meteosat{
+    name: &'static str,
+    /// WMS图层名
+    layer: &'static str,
+    /// 星下点经度
+    longitude: f64,
+}
+
+/// Meteosat 0°
+pub static METEOSAT_0DEG: Meteosat = Meteosat{ name: "meteosat", layer: "msg_fes:rgb_naturalenhncd", longitude: 0.0 };
+
+/// Meteosat 印度洋(东经45.5°)
+pub static METEOSAT_IODC: Meteosat = Meteosat{ name: "meteosat_iodc", layer: "msg_iodc:rgb_naturalenhncd", longitude: 45.5 };
+
+impl SatelliteSource for Meteosat{
+    fn name(&self) -> &'static str{
+        self.name
+    }
+
+    fn longitude(&self) -> f64{
+        self.longitude
+    }
+
+    fn projection(&self) -> Projection{
+        Projection::Orthographic
+    }
+
+    fn base_url<'a>(&self, cfg: &'a Config) -> &'a str{
+        &cfg.download_url_meteosat
+    }
+
+    fn grid_sizes(&self) -> &'static [u32]{
+        &[1, 2, 4]
+    }
+
+    fn tile_size(&self) -> u32{
+        1024
+    }
+
+    fn tile_url(&self, base_url: &str, time: &PrimitiveDateTime, d: u32, x: u32, y: u32) -> String{
+        format_url(base_url, &TileRequest::new(self, time, d, x, y))
+    }
+
+    fn interval(&self) -> u32{
+        15
+    }
+
+    /// 全圆盘图每15分钟一张, 从30分钟前以15分钟倒推
+    fn timestamps(&self, now: OffsetDateTime) -> Vec<PrimitiveDateTime>{
+        let hour = Date::from_calendar_date(now.year(), now.month(), now.day())
+            .and_then(|today| today.with_hms(now.hour(), (now.minute()/15)*15, 0));
+        match hour{
+            Ok(hour) => (2..=5).map(|i| hour.sub(Duration::from_secs(60*15*i))).collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+/// 一张WMS瓦片的请求参数
+pub struct TileRequest<'a>{
+    /// WMS图层名
+    pub layer: &'a str,
+    /// 正射投影的中心经度
+    pub longitude: f64,
+    /// 瓦片在投影坐标中的范围(米) [min_x, min_y, max_x, max_y]
+    pub bbox: [f64; 4],
+    /// 瓦片边长(像素)
+    pub size: u32,
+    /// UTC时间
+    pub time: &'a PrimitiveDateTime,
+}
+
+impl<'a> TileRequest<'a>{
+    /// d x d网格中第x列、第y行的瓦片
+    pub fn new(meteosat: &'a Meteosat, time: &'a PrimitiveDateTime, d: u32, x: u32, y: u32) -> Self{
+        let tile = EARTH_RADIUS * 2.0 / d as f64;
+        let min_x = -EARTH_RADIUS + tile * x as f64;
+        let max_y = EARTH_RADIUS - tile * y as f64;
+        Self { layer: meteosat.layer, longitude: meteosat.longitude, bbox: [min_x, max_y - tile, min_x + tile, max_y], size: meteosat.tile_size(), time }
+    }
+}
+
+/// 下载d x d张1024x1024的图, time为UTC时间
+pub fn download<C>(meteosat: &Meteosat, url: &str, d: u32, time: PrimitiveDateTime, callback: C) -> Result<RgbaImage>
+where
+    C: Fn(u32, u32) + 'static,
+{
+    source::download(meteosat, url, d, time, callback)
+}
+
+pub fn format_url(url: &str, request: &TileRequest) -> String {
+    let [min_x, min_y, max_x, max_y] = request.bbox;
+    let time = request.time;
+    format!("{}?service=WMS&version=1.1.1&request=GetMap&layers={}&styles=&format=image/jpeg&srs=AUTO:42003,9001,{},0&bbox={},{},{},{}&width={}&height={}&time={}-{:02}-{:02}T{:02}:{:02}:00Z",
+        url, request.layer, request.longitude, min_x, min_y, max_x, max_y, request.size, request.size,
+        time.year(), time.month() as u8, time.day(), time.hour(), time.minute())
+}
+
+/// 下载最新图片, 根据cfg.satellite_name选择0°或印度洋
+pub fn download_lastest<C:Fn(u32, u32) + 'static>(cfg: &Config, d:u32, callback:C ) -> Result<Option<(String, PrimitiveDateTime, RgbaImage)>>{
+    let meteosat = if cfg.satellite_name == METEOSAT_IODC.name{ &METEOSAT_IODC }else{ &METEOSAT_0DEG };
+    source::download_lastest(meteosat, cfg, meteosat.grid_size(d), callback)
+}
+
+#[cfg(test)]
+mod tests{
+    use time::macros::datetime;
+    use super::*;
+
+    #[test]
+    fn tile_url_bbox(){
+        let time = datetime!(2024-10-29 12:15);
+        // 2x2网格右上角的瓦片为圆盘的第一象限
+        let url = METEOSAT_0DEG.tile_url("https://view.eumetsat.int/geoserver/wms", &time, 2, 1, 0);
+        assert_eq!(url, "https://view.eumetsat.int/geoserver/wms?service=WMS&version=1.1.1&request=GetMap&layers=msg_fes:rgb_naturalenhncd&styles=&format=image/jpeg&srs=AUTO:42003,9001,0,0&bbox=0,0,6378137,6378137&width=1024&height=1024&time=2024-10-29T12:15:00Z");
+    }
+}
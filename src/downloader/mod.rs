@@ -1,13 +1,19 @@
-use std::time::Instant;
+use std::{collections::HashMap, time::Instant};
 use anyhow::{anyhow, Result};
 use async_std::task::spawn_blocking;
-use chrono::{Local, Timelike};
-use image::{buffer::ConvertBuffer, imageops::resize, GenericImage, RgbImage, RgbaImage};
+use chrono::Local;
+use image::{imageops::resize, RgbImage, RgbaImage};
 use log::{error, info};
 pub mod h8;
 pub mod fy4x;
+pub mod cache;
+pub mod composite;
+pub mod fetcher;
+pub mod goes;
+pub mod meteosat;
+pub mod source;
 
-use crate::{app::{get_current_wallpaper, get_screen_size, get_wallpaper_file_path}, config::Config};
+use crate::{app::{get_current_wallpaper, get_monitor_wallpaper_file_path, get_monitors, get_wallpaper_file_path, Monitor}, config::Config, downloader::source::{ImagePart, Visible}, history::WallpaperHistory, night, overlay::{self, OverlayOptions}, layout::{self, LayoutOptions}, projection::lonlat_to_pixel, region::Region, span::SpanLayout};
 
 static DOWNLOADING: std::sync::RwLock<bool> = std::sync::RwLock::new(false);
 
@@ -26,147 +32,151 @@ pub fn set_downlading(d: bool){
 }
 
 pub fn format_time_str(download_name:&str, d: u32, year:i32, month:u8, day:u8, hour: u8, minute:u8) -> String{
-    format!("{}-D{}-UTC-{}年-{}月-{}日-{}时-{:02}分", download_name, d, year, month, day, hour, (minute/15)*15)
+    // 使用图片时间的准确分钟, 10分钟一张的卫星每个时间也各不相同
+    format!("{}-D{}-UTC-{}年-{}月-{}日-{}时-{:02}分", download_name, d, year, month, day, hour, minute)
 }
 
-fn set_wallpaper<C:Fn(u32, u32) + 'static>(cfg:&Config, width: u32, height: u32, half: bool, callback: C) -> Result<String>{
-    info!("set_wallpaper>>准备下载 {width}x{height}...");
-    //创建一张黑色背景图片
-    let mut paper = RgbImage::new(width, height);
-    let d = if height > 1080||half { 4 }else{ 2};
-    let image = 
-        match cfg.satellite_name.as_str(){
-            "h8" => h8::download_lastest(cfg, d, callback)?,
-            _ => fy4x::download_lastest(cfg, d, callback)?,
+/// 下载图片并为每个显示器生成壁纸, 返回图片时间
+fn set_wallpaper<C:Fn(u32, u32) + 'static>(cfg:&Config, monitors: &[Monitor], half: bool, callback: C) -> Result<String>{
+    // 横跨模式下把所有显示器当作一张大画布下载和布局, 最后再按显示器裁剪
+    let span = if cfg.span_monitors && monitors.len() > 1 { Some(SpanLayout::new(monitors, cfg.bezel_gap)) } else { None };
+    let all_monitors = monitors;
+    let span_monitor;
+    let monitors = match &span{
+        Some(span) => {
+            info!("set_wallpaper>>横跨模式 画布:{}x{} {:?}", span.width, span.height, span.rects);
+            span_monitor = [Monitor{ id: String::from("span"), x: 0, y: 0, width: span.width, height: span.height, scale: 1.0 }];
+            &span_monitor[..]
+        }
+        None => monitors,
+    };
+    // 按最大的显示器选择下载的图片大小
+    let (width, height) = monitors.iter().map(|m| (m.width, m.height)).max_by_key(|(w, h)| w * h).unwrap_or((1920, 1080));
+    info!("set_wallpaper>>准备下载 {width}x{height} 显示器:{}个...", monitors.len());
+    let region = Region::from_config(cfg);
+    let now = Local::now().time();
+    // 全圆盘图的星下点和投影, 全球拼接图为None
+    let (image, disk) = if cfg.satellite_name == composite::NAME{
+        //全球图铺满屏幕宽度, 裁剪区域时按区域宽度放大
+        let map_width = match region.and_then(|r| r.rect_on_map(10000, 5000)){
+            Some((_, _, w, _)) => width as f64 * 10000.0 / w as f64,
+            None => width as f64,
+        };
+        let d = cfg.grid_size.unwrap_or_else(|| composite::grid_size(map_width));
+        info!("set_wallpaper>>全球图宽度:{map_width} d={d}");
+        (composite::download_lastest(cfg, d, callback)?.map(|(timestr, time, map)| (timestr, time, ImagePart::full(map))), None)
+    }else{
+        let source = source::get_source(&cfg.satellite_name);
+        //只下载需要显示的瓦片, 多个显示器时取所有显示器的并集
+        let mut visible: Option<Visible> = None;
+        let mut diameter: f64 = 0.0;
+        let options = LayoutOptions{ half, region, disk: Some(source.disk()), layout: cfg.layout.clone() };
+        for monitor in monitors{
+            let screen = (monitor.width, monitor.height);
+            let v = layout::visible(screen, &options, now);
+            visible = Some(visible.map_or(v, |visible| visible.union(&v)));
+            diameter = diameter.max(layout::diameter(screen, &options, now));
+        }
+        let visible = visible.unwrap_or(Visible::ALL);
+        //按屏幕上地球的直径选择网格大小
+        let d = match cfg.grid_size{
+            Some(d) => source.grid_size(d),
+            None => source.grid_for(diameter as u32),
         };
+        info!("set_wallpaper>>可见部分:{:?} 地球直径:{diameter} d={d}", visible);
+        (source::download_lastest_part(source, cfg, d, visible, callback)?, Some(source.disk()))
+    };
     if image.is_none(){
         error!("set_wallpaper>>图片下载失败 {width}x{height} image.is_none()");
         return Err(anyhow!("图片下载失败."));
     }
-    let (timestr, mut image) = image.unwrap();
-    let mut image:RgbImage = image.convert();
-
-    //横屏模式
-    let _image = if height < width || !half{
-        // 图片稍微缩小一点
-        let scale = if !half{
-            (height as f32 * 0.9) / image.height() as f32
-        }else{
-            (width as f32 * 0.95) / image.width() as f32
-        };
-        let mut final_width = image.width() as f32 * scale;
-        let mut final_height = image.height() as f32*scale;
-        //不超过目标图片大小
-        if final_width > paper.width() as f32{
-            let s = paper.width() as f32 / final_width;
-            final_width = final_width * s;
-            final_height = final_height * s;
-        }
-
-        if final_height > paper.height() as f32{
-            let s = paper.height() as f32 / final_height;
-            final_width = final_width * s;
-            final_height = final_height * s;
-        }
-
-        info!("set_wallpaper>>开始缩放 scale={scale} 目标大小:{final_width}x{final_height}...");
+    let (timestr, time, mut image) = image.unwrap();
+    if cfg.night_lights{
+        //叠加夜间灯光
         let t = Instant::now();
-        let mut image = fast_resize(&image, final_width as u32, final_height as u32);
-        info!("set_wallpaper>>图片缩放成功 image:{}x{} paper:{}x{} half:{half} 耗时:{}ms", image.width(), image.height(), paper.width(), paper.height(), t.elapsed().as_millis());
-
-        // 复制到桌面背景中
-        if half{
-            //复制上半块
-            let x = (paper.width()-image.width())/2;
-            let y = (paper.height() as f32 * 0.05) as u32;
-            //要复制的高度
-            let ch = paper.height()-y;
-            //要复制的图像
-            image = image.sub_image(0, 0, image.width(), ch).to_image();
-            paper.sub_image(x, y, image.width(), image.height()).copy_from(&image, 0, 0)?;
-        }else{
-            let x = (paper.width()-image.width())/2;
-            let y = (paper.height()-image.height())/2;
-            paper.sub_image(x, y, image.width(), image.height()).copy_from(&image, 0, 0)?;
-        }
-        image
-    }else{
-        //竖屏: 地球直径取屏幕高度(%)，上午取地球右半部分，下午取地球左半部分
-        
-        use chrono::Local;
-        let time = Local::now();
-        let mut offset_x = 0;
-        let mut left = true;
-        if time.hour() <= 12 {
-            //取地球右半部分
-            let w = ((image.width() as f64 / 2.0)*1.06) as u32;
-            let x = image.width() - w;
-            image = image.sub_image(x, 0, w, image.height()).to_image();
-        } else {
-            //取地球左半部分
-            let w = ((image.width() as f64 / 2.0)*1.06) as u32;
-            image = image.sub_image(0, 0, w, image.height()).to_image();
-            left = false;
-        }
-
-        //缩放，最大不超过屏幕高度
-        let mut final_width = image.width() as f32 * 0.95;
-        let mut final_height = image.height() as f32 * 0.95;
-        //不超过目标图片大小
-        if final_width > paper.width() as f32{
-            let s = paper.width() as f32 / final_width;
-            final_width = final_width * s;
-            final_height = final_height * s;
-        }
-
-        if final_height > paper.height() as f32{
-            let s = paper.height() as f32 / final_height;
-            final_width = final_width * s;
-            final_height = final_height * s;
+        match night::load_night_lights(cfg){
+            Ok(lights) => {
+                match disk{
+                    Some(disk) => night::apply_to_disk(&mut image, disk, time, &lights, cfg.twilight_degrees),
+                    None => night::apply_to_map(&mut image.image, time, &lights, cfg.twilight_degrees),
+                }
+                info!("set_wallpaper>>夜间灯光叠加完成 耗时:{}ms", t.elapsed().as_millis());
+            }
+            Err(err) => error!("夜间灯光图读取失败:{:?}", err),
         }
+    }
 
-        info!("set_wallpaper>>开始缩放 目标大小:{final_width}x{final_height}...");
-        let t = Instant::now();
-        let image = fast_resize(&image, final_width as u32, final_height as u32);
-        info!("set_wallpaper>>图片缩放成功 image:{}x{} paper:{}x{} half:{half} 耗时:{}ms", image.width(), image.height(), paper.width(), paper.height(), t.elapsed().as_millis());
-
-        if !left{
-            offset_x = (paper.width() - image.width()) as usize;
+    let overlay_options = OverlayOptions::from_config(cfg);
+    let mut papers = vec![];
+    for monitor in monitors{
+        let screen = (monitor.width, monitor.height);
+        let options = LayoutOptions{ half, region, disk, layout: cfg.layout.clone() };
+        let mut paper = layout::compose(&image, screen, &options, now)?;
+        if cfg.overlay_enabled && cfg.overlay_show_longitude{
+            //在星下点(圆盘中心)画标记
+            let full = (image.width, image.height);
+            let point = disk.and_then(|disk| lonlat_to_pixel(disk.sub_lon, 0.0, disk, full.0, full.1))
+                .and_then(|(px, py)| layout::paper_position(full, screen, &options, now, px, py));
+            if let Some((x, y)) = point{
+                overlay::draw_marker(&mut paper, x, y, &overlay_options);
+            }
         }
-
-        //拼接
-        let offset_y = ((paper.height() - image.height()) / 2) as usize;
-        let ew = image.width() as usize;
-        for (y, buf) in image.chunks(ew * 3).enumerate() {
-            if (y + offset_y) < paper.height() as usize {
-                let offset = width as usize * 3 * (y + offset_y) + offset_x * 3;
-                if let Some(s) = paper.get_mut(offset..offset + buf.len()) {
-                    if s.len() == buf.len() {
-                        s.copy_from_slice(buf);
-                    }
+        info!("set_wallpaper>>图片准备完成 {} paper:{}x{} half:{half}", monitor.id, paper.width(), paper.height());
+        papers.push(paper);
+    }
+    let monitors = all_monitors;
+    if let Some(span) = &span{
+        papers = span.slice(&papers[0], monitors)?;
+    }
+    if cfg.overlay_enabled{
+        //绘制文字信息, 横跨模式只画在第一个显示器上
+        let lines = overlay::overlay_lines(cfg, time, disk.map(|d| d.sub_lon));
+        let count = if span.is_some() { 1 } else { papers.len() };
+        //字体只读取一次, 所有显示器共用
+        match overlay::load_font(&overlay_options.font){
+            Ok(font) => {
+                for paper in papers.iter_mut().take(count){
+                    overlay::draw_overlay(paper, &lines, &font, &overlay_options);
                 }
-            } else {
-                break;
             }
+            Err(err) => error!("文字绘制失败:{:?}", err),
         }
-        image
-    };
+    }
 
-    info!("set_wallpaper>>图片准备完成 paper:{}x{} half:{half}", paper.width(), paper.height());
     let wallpaper_file_path = get_wallpaper_file_path();
     info!("set_wallpaper>>wallpaper_file_path {wallpaper_file_path}");
-    paper.save(&wallpaper_file_path)?;
+    papers[0].save(&wallpaper_file_path)?;
+    //保存历史壁纸
+    if let Some(history) = WallpaperHistory::from_config(cfg){
+        if let Err(err) = history.archive(&cfg.satellite_name, &timestr, &papers[0]){
+            error!("历史壁纸保存失败:{:?}", err);
+        }
+    }
     // 设置锁屏
 
     info!("开始调用set_lock_screen_image>>>>>>>>>>>>");
 
     let loc_res = super::app::set_lock_screen_image(&wallpaper_file_path);
     info!("锁屏设置结果: {:?}", loc_res);
-    
-    info!("开始调用set_wallpaper_from_path>>>>>>>>>>>>");
-    let loc_res = super::app::set_wallpaper_from_path(&wallpaper_file_path);
-    info!("壁纸设置结果: {:?}", loc_res);
-    loc_res?;
+
+    if monitors.len() == 1{
+        info!("开始调用set_wallpaper_from_path>>>>>>>>>>>>");
+        let loc_res = super::app::set_wallpaper_from_path(&wallpaper_file_path);
+        info!("壁纸设置结果: {:?}", loc_res);
+        loc_res?;
+    }else{
+        //每个显示器一张壁纸
+        let mut wallpapers = vec![(monitors[0].clone(), wallpaper_file_path)];
+        for (i, (monitor, paper)) in monitors.iter().zip(papers.iter()).enumerate().skip(1){
+            let path = get_monitor_wallpaper_file_path(i);
+            paper.save(&path)?;
+            wallpapers.push((monitor.clone(), path));
+        }
+        info!("开始调用set_monitor_wallpapers>>>>>>>>>>>>");
+        let loc_res = super::app::set_monitor_wallpapers(&wallpapers);
+        info!("壁纸设置结果: {:?}", loc_res);
+        loc_res?;
+    }
     Ok(timestr)
 }
 
@@ -175,8 +185,8 @@ pub async fn set_wallpaper_default(cfg: &mut Config){
         info!("壁纸正在下载中, 请稍后..");
         return;
     }
-    // 获取屏幕宽高
-    let (screen_width, screen_height) = get_screen_size();
+    // 获取所有显示器
+    let monitors = get_monitors();
 
     set_downlading(true);
 
@@ -193,9 +203,9 @@ pub async fn set_wallpaper_default(cfg: &mut Config){
     }
 
     let ret = spawn_blocking(move ||{
-        let mut cfg = cfg_clone;
+        let cfg = cfg_clone;
         info!("调用 set_wallpaper >> step 002");
-        let ret = set_wallpaper(&mut cfg, screen_width as u32, screen_height as u32, display_type==2, |i,t|{
+        let ret = set_wallpaper(&cfg, &monitors, display_type==2, |i,t|{
                 info!("正在下载: {}/{}", i, t);
         });
         info!("调用 set_wallpaper >> step 003");
@@ -219,7 +229,7 @@ pub async fn set_wallpaper_default(cfg: &mut Config){
     info!("下载结束....");
 }
 
-fn fast_resize(src:&RgbImage, dst_width: u32, dst_height: u32) -> RgbImage{
+pub fn fast_resize(src:&RgbImage, dst_width: u32, dst_height: u32) -> RgbImage{
     let src = src.clone();
     fast_resize_block(&src, dst_width, dst_height)
 }
@@ -252,10 +262,57 @@ fn fast_resize_block(src:&RgbImage, dst_width: u32, dst_height: u32) -> RgbImage
     resize(src, dst_width, dst_height, image::imageops::FilterType::Lanczos3)
 }
 
+/// 缩放带透明通道的图片, 缩放时按透明度加权, 透明部分的颜色不会渗到边缘
+pub fn fast_resize_rgba(src:&RgbaImage, dst_width: u32, dst_height: u32) -> RgbaImage{
+    let mut dst_image = fast_image_resize::images::Image::new(
+        dst_width,
+        dst_height,
+        fast_image_resize::PixelType::U8x4,
+    );
+    let mut src_image = fast_image_resize::images::Image::new(
+        src.width(),
+        src.height(),
+        fast_image_resize::PixelType::U8x4,
+    );
+    src_image.buffer_mut().copy_from_slice(src);
+    let mut resizer = fast_image_resize::Resizer::new();
+    let r = resizer.resize(&src_image, &mut dst_image, None);
+
+    match r{
+        Ok(_) => {
+            if let Some(img) = RgbaImage::from_raw(dst_image.width(), dst_image.height(), dst_image.buffer().to_vec()){
+                return img;
+            }
+        }
+        Err(err) => {
+            error!("图片快速缩放失败:{:?}", err);
+        }
+    }
+    resize(src, dst_width, dst_height, image::imageops::FilterType::Lanczos3)
+}
+
 pub fn current_time_str() -> String{
     chrono::Local::now().format("%Y/%m/%d %H:%M:%S").to_string()
 }
 
+/// 判断瓦片是否为"No Image"之类的占位图: 绝大部分像素是同一种非黑色
+///
+/// 向日葵8号对尚未发布的时间返回灰色的"No Image"图片, 太空部分的瓦片是纯黑色, 属于正常瓦片
+pub fn is_placeholder_tile(img: &RgbaImage) -> bool{
+    let mut counts: HashMap<[u8; 4], u32> = HashMap::new();
+    let mut total = 0;
+    for pixel in img.pixels().step_by(7){
+        *counts.entry(pixel.0).or_default() += 1;
+        total += 1;
+    }
+    let Some((color, count)) = counts.into_iter().max_by_key(|(_, count)| *count) else{
+        return true;
+    };
+    // 黑色的太空和透明的圆盘外部分都是正常瓦片
+    let is_space = color[3] == 0 || (color[0] < 16 && color[1] < 16 && color[2] < 16);
+    !is_space && count as f64 / total as f64 > 0.9
+}
+
 //取一张图片
 pub fn download_image(url: &str) -> Result<RgbaImage> {
     info!("download_image {}", url);
@@ -291,4 +348,41 @@ fn download_image_sync(url: &str) -> Result<RgbaImage> {
     let img = image::load_from_memory(image_data)?.to_rgba8();
     info!("download_image {} OK:{}x{}", url, img.width(), img.height());
     Ok(img)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests{
+    use image::Rgba;
+    use super::*;
+
+    #[test]
+    fn grey_no_image_tile_is_placeholder(){
+        // 灰色背景上有少量深色文字
+        let mut tile = RgbaImage::from_pixel(550, 550, Rgba([128, 128, 128, 255]));
+        for x in 200..350{
+            for y in 260..290{
+                tile.put_pixel(x, y, Rgba([40, 40, 40, 255]));
+            }
+        }
+        assert!(is_placeholder_tile(&tile));
+    }
+
+    #[test]
+    fn black_space_tile_is_valid(){
+        let tile = RgbaImage::from_pixel(550, 550, Rgba([0, 0, 0, 255]));
+        assert!(!is_placeholder_tile(&tile));
+    }
+
+    #[test]
+    fn transparent_tile_is_valid(){
+        let mut tile = RgbaImage::from_pixel(550, 550, Rgba([255, 255, 255, 0]));
+        tile.put_pixel(549, 549, Rgba([90, 120, 200, 255]));
+        assert!(!is_placeholder_tile(&tile));
+    }
+
+    #[test]
+    fn earth_tile_is_valid(){
+        let tile = RgbaImage::from_fn(550, 550, |x, y| Rgba([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8, 255]));
+        assert!(!is_placeholder_tile(&tile));
+    }
+}
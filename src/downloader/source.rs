@@ -0,0 +1,286 @@
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Timelike};
+use image::{GenericImage, RgbaImage};
+use log::{error, info, warn};
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime};
+
+use crate::{config::Config, downloader::{cache::TileKey, download_image, is_placeholder_tile, fetcher::{fetch_tiles, FetchOptions}, format_time_str, fy4x, goes, h8, meteosat}, projection::{Disk, Projection}};
+
+/// 卫星图片来源
+///
+/// 每颗卫星只需要描述瓦片地址、可用时间和网格大小，下载和拼接由本模块完成。
+pub trait SatelliteSource: Send + Sync{
+    /// 卫星名字，对应Config.satellite_name
+    fn name(&self) -> &'static str;
+
+    /// 星下点经度(东经为正)
+    fn longitude(&self) -> f64;
+
+    /// 全圆盘图的投影方式
+    fn projection(&self) -> Projection{
+        Projection::Geostationary
+    }
+
+    /// 全圆盘图的星下点和投影
+    fn disk(&self) -> Disk{
+        Disk{ sub_lon: self.longitude(), projection: self.projection() }
+    }
+
+    /// 配置中的图片下载地址
+    fn base_url<'a>(&self, cfg: &'a Config) -> &'a str;
+
+    /// 支持的网格大小(每行瓦片数)，从小到大
+    fn grid_sizes(&self) -> &'static [u32];
+
+    /// 不超过d的最大网格大小, d比所有网格都小时返回最小的网格
+    fn grid_size(&self, d: u32) -> u32{
+        let sizes = self.grid_sizes();
+        sizes.iter().rev().find(|s| **s <= d).copied().unwrap_or(sizes[0])
+    }
+
+    /// 拼接后直径不小于diameter的最小网格, 都不够大时返回最大的网格
+    fn grid_for(&self, diameter: u32) -> u32{
+        let sizes = self.grid_sizes();
+        sizes.iter().find(|d| **d * self.tile_size() >= diameter).copied().unwrap_or(sizes[sizes.len() - 1])
+    }
+
+    /// 单张瓦片的边长(像素)
+    fn tile_size(&self) -> u32;
+
+    /// 第x列、第y行瓦片的下载地址, time为UTC时间
+    fn tile_url(&self, base_url: &str, time: &PrimitiveDateTime, d: u32, x: u32, y: u32) -> String;
+
+    /// 拍摄间隔(分钟)
+    fn interval(&self) -> u32;
+
+    /// 候选的图片时间(UTC)，从新到旧排列
+    fn timestamps(&self, now: OffsetDateTime) -> Vec<PrimitiveDateTime>;
+
+    /// 固定时间的候选图片时间, 从time所在的时段开始往前倒推6个间隔
+    fn pinned_timestamps(&self, time: PrimitiveDateTime) -> Vec<PrimitiveDateTime>{
+        let interval = self.interval() as u8;
+        match time.replace_second(0).and_then(|t| t.replace_minute((t.minute()/interval)*interval)){
+            Ok(slot) => (0..6).map(|i| slot - Duration::from_secs(60 * self.interval() as u64 * i)).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// 瓦片是否有效, 默认把"No Image"之类的占位图当作图片尚未发布
+    fn is_valid_tile(&self, img: &RgbaImage) -> bool{
+        !is_placeholder_tile(img)
+    }
+
+    /// 最新可下载的图片时间
+    fn latest_time(&self, cfg: &Config) -> Result<PrimitiveDateTime>{
+        self.probe_latest_time(cfg, OffsetDateTime::now_utc())
+    }
+
+    /// 依次尝试下载候选时间的第一张瓦片, 返回第一个有效的时间
+    fn probe_latest_time(&self, cfg: &Config, now: OffsetDateTime) -> Result<PrimitiveDateTime>{
+        self.probe_times(cfg, self.timestamps(now))
+    }
+
+    /// 依次尝试下载times中每个时间的第一张瓦片, 返回第一个有效的时间
+    fn probe_times(&self, cfg: &Config, times: Vec<PrimitiveDateTime>) -> Result<PrimitiveDateTime>{
+        let d = self.grid_sizes()[0];
+        for time in times{
+            match download_image(&self.tile_url(self.base_url(cfg), &time, d, 0, 0)){
+                Ok(img) if self.is_valid_tile(&img) => return Ok(time),
+                Ok(_) => info!("卫星图片尚未发布(占位图)，尝试下载更早的图片."),
+                Err(err) => {
+                    error!("download_image失败: {:?}", err);
+                    info!("卫星图片不存在，尝试下载更早的图片.");
+                }
+            }
+        }
+        Err(anyhow!("{}: 没有可下载的图片", self.name()))
+    }
+}
+
+/// 已注册的卫星, 第一个为默认卫星
+static SOURCES: &[&dyn SatelliteSource] = &[&fy4x::Fy4b, &h8::H8, &goes::GOES_EAST, &goes::GOES_WEST, &meteosat::METEOSAT_0DEG, &meteosat::METEOSAT_IODC];
+
+pub fn sources() -> &'static [&'static dyn SatelliteSource]{
+    SOURCES
+}
+
+/// 根据名字查找卫星，找不到时返回默认卫星
+pub fn get_source(name: &str) -> &'static dyn SatelliteSource{
+    SOURCES.iter().find(|s| s.name() == name).copied().unwrap_or(SOURCES[0])
+}
+
+/// 图片中需要显示的部分, 取值0~1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Visible{
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl Visible{
+    pub const ALL: Visible = Visible{ left: 0.0, top: 0.0, right: 1.0, bottom: 1.0 };
+
+    /// 和可见部分相交的瓦片范围 (x0, y0, x1, y1), 不包含x1、y1
+    pub fn tiles(&self, d: u32) -> (u32, u32, u32, u32){
+        let start = |v: f64| ((v.clamp(0.0, 1.0) * d as f64).floor() as u32).min(d - 1);
+        let end = |v: f64| ((v.clamp(0.0, 1.0) * d as f64).ceil() as u32).max(1);
+        let (x0, y0) = (start(self.left), start(self.top));
+        (x0, y0, end(self.right).max(x0 + 1), end(self.bottom).max(y0 + 1))
+    }
+
+    /// 同时包含两个可见部分的最小范围
+    pub fn union(&self, other: &Visible) -> Visible{
+        Visible{
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+}
+
+/// 完整图片中的一部分, 只下载可见瓦片时不需要分配整张图的内存
+#[derive(Clone, Debug)]
+pub struct ImagePart{
+    pub image: RgbaImage,
+    /// 在完整图片中的左上角
+    pub x: u32,
+    pub y: u32,
+    /// 完整图片的宽高
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImagePart{
+    /// 整张图片
+    pub fn full(image: RgbaImage) -> Self{
+        let (width, height) = image.dimensions();
+        Self { image, x: 0, y: 0, width, height }
+    }
+
+    /// 和完整图片中(x, y, w, h)范围相交的部分, 用完整图片的坐标表示, 不相交时返回None
+    pub fn intersect(&self, rect: (u32, u32, u32, u32)) -> Option<(u32, u32, u32, u32)>{
+        let (x0, y0) = (rect.0.max(self.x), rect.1.max(self.y));
+        let x1 = (rect.0 + rect.2).min(self.x + self.image.width());
+        let y1 = (rect.1 + rect.3).min(self.y + self.image.height());
+        if x1 <= x0 || y1 <= y0{
+            return None;
+        }
+        Some((x0, y0, x1 - x0, y1 - y0))
+    }
+}
+
+/// 下载d x d张瓦片并拼接成一张图
+pub fn download<C>(source: &dyn SatelliteSource, url: &str, d: u32, time: PrimitiveDateTime, callback: C) -> Result<RgbaImage>
+where
+    C: Fn(u32, u32) + 'static,
+{
+    Ok(download_part(source, url, d, time, Visible::ALL, &FetchOptions::default(), callback)?.image)
+}
+
+/// 只下载和可见部分相交的瓦片, 拼接成只包含这些瓦片的图
+pub fn download_part<C>(source: &dyn SatelliteSource, url: &str, d: u32, time: PrimitiveDateTime, visible: Visible, options: &FetchOptions, callback: C) -> Result<ImagePart>
+where
+    C: Fn(u32, u32) + 'static,
+{
+    /*
+    瓦片按行排列, 序号 = y*d + x:
+    00,10,20,30
+    01,11,21,31
+    02,12,22,32
+    03,13,23,33
+     */
+    let (x0, y0, x1, y1) = visible.tiles(d);
+    let tiles: Vec<(u32, u32)> = (y0..y1).flat_map(|y| (x0..x1).map(move |x| (x, y))).collect();
+    info!("开始下载图片 共{}张(全部{}张)...", tiles.len(), d*d);
+    let t = Instant::now();
+    let key = |(x, y): (u32, u32)| TileKey{ satellite: source.name(), time: &time, d, x, y };
+    //先读取缓存, 只下载缓存中没有的瓦片
+    let mut images: Vec<Option<RgbaImage>> = tiles.iter()
+        .map(|tile| options.cache.as_ref().and_then(|cache| cache.get(&key(*tile))))
+        .collect();
+    let missing: Vec<usize> = (0..tiles.len()).filter(|i| images[*i].is_none()).collect();
+    info!("缓存中已有{}张", tiles.len() - missing.len());
+    if !missing.is_empty(){
+        let urls = missing.iter().map(|i| source.tile_url(url, &time, d, tiles[*i].0, tiles[*i].1)).collect();
+        let downloaded = fetch_tiles(urls, options, callback)?;
+        if let Some(i) = downloaded.iter().position(|img| !source.is_valid_tile(img)){
+            return Err(anyhow!("瓦片尚未发布: {}", source.tile_url(url, &time, d, tiles[missing[i]].0, tiles[missing[i]].1)));
+        }
+        for (i, img) in missing.into_iter().zip(downloaded){
+            if let Some(cache) = options.cache.as_ref(){
+                if let Err(err) = cache.put(&key(tiles[i]), &img){
+                    warn!("瓦片缓存保存失败:{:?}", err);
+                }
+            }
+            images[i] = Some(img);
+        }
+        if let Some(cache) = options.cache.as_ref(){
+            let _ = cache.evict();
+        }
+    }
+    let images: Vec<RgbaImage> = images.into_iter().flatten().collect();
+
+    info!("图片下载完成 共{}张. 耗时:{}ms", images.len(), t.elapsed().as_millis());
+    let t = Instant::now();
+    let (width, height) = (images[0].width(), images[0].height());
+    //只分配可见瓦片的范围
+    let mut big_img = RgbaImage::new(width*(x1 - x0), height*(y1 - y0));
+    for ((x, y), img) in tiles.iter().zip(images.iter()){
+        big_img.sub_image((x - x0)*width, (y - y0)*height, img.width(), img.height()).copy_from(img, 0, 0)?;
+    }
+    info!("图片合并完成 {}x{} 完整大小{}x{}. 耗时:{}ms", big_img.width(), big_img.height(), width*d, height*d, t.elapsed().as_millis());
+    Ok(ImagePart{ image: big_img, x: x0*width, y: y0*height, width: width*d, height: height*d })
+}
+
+/// 固定壁纸时间的格式
+pub const PINNED_TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// 解析固定壁纸时间(UTC)
+pub fn parse_pinned_time(s: &str) -> Result<PrimitiveDateTime>{
+    let t = chrono::NaiveDateTime::parse_from_str(s.trim(), PINNED_TIME_FORMAT)
+        .map_err(|err| anyhow!("时间格式错误:{s} 应为{PINNED_TIME_FORMAT} {:?}", err))?;
+    utc_time(t.year(), t.month() as u8, t.day() as u8, t.hour() as u8, t.minute() as u8)
+}
+
+/// 壁纸使用的图片时间
+///
+/// 设置了pinned_time时使用不晚于该时间的最近一张图片, 设置了days_ago时使用N天前同一时刻的图片, 否则使用最新图片
+pub fn wallpaper_time(source: &dyn SatelliteSource, cfg: &Config) -> Result<PrimitiveDateTime>{
+    if !cfg.pinned_time.trim().is_empty(){
+        let pinned = parse_pinned_time(&cfg.pinned_time)?;
+        info!("{} 固定壁纸时间:{pinned}", source.name());
+        return source.probe_times(cfg, source.pinned_timestamps(pinned));
+    }
+    let time = source.latest_time(cfg)?;
+    if cfg.days_ago > 0{
+        info!("{} 使用{}天前的图片", source.name(), cfg.days_ago);
+        return Ok(time - Duration::from_secs(cfg.days_ago as u64 * 24 * 3600));
+    }
+    Ok(time)
+}
+
+/// 下载最新图片，和当前壁纸时间相同时返回None
+pub fn download_lastest<C:Fn(u32, u32) + 'static>(source: &dyn SatelliteSource, cfg: &Config, d:u32, callback:C ) -> Result<Option<(String, PrimitiveDateTime, RgbaImage)>>{
+    Ok(download_lastest_part(source, cfg, d, Visible::ALL, callback)?.map(|(timestr, time, part)| (timestr, time, part.image)))
+}
+
+/// 下载最新图片(或配置中指定时间的图片)的可见部分，和当前壁纸时间相同时返回None
+pub fn download_lastest_part<C:Fn(u32, u32) + 'static>(source: &dyn SatelliteSource, cfg: &Config, d:u32, visible: Visible, callback:C ) -> Result<Option<(String, PrimitiveDateTime, ImagePart)>>{
+    let time = wallpaper_time(source, cfg)?;
+    let timestr = format_time_str(source.name(), d, time.year(), time.month() as u8, time.day(), time.hour(), time.minute());
+    info!("时间:{}", timestr);
+    if cfg.current_wallpaper_date == timestr{
+        warn!("壁纸无需重复下载");
+        return Ok(None);
+    }
+    let img = download_part(source, source.base_url(cfg), d, time, visible, &FetchOptions::from_config(cfg), callback)?;
+    Ok(Some((timestr, time, img)))
+}
+
+/// 由年月日时分构造UTC时间
+pub fn utc_time(year: i32, month: u8, day: u8, hour: u8, minute: u8) -> Result<PrimitiveDateTime>{
+    Ok(Date::from_calendar_date(year, Month::try_from(month)?, day)?.with_hms(hour, minute, 0)?)
+}
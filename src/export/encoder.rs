@@ -0,0 +1,399 @@
+use std::{fs::{self, File}, io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}, process::{Child, ChildStdin, Command, Stdio}};
+use anyhow::{anyhow, Result};
+use image::{buffer::ConvertBuffer, codecs::{gif::{GifEncoder, Repeat}, png::PngEncoder, webp::WebPEncoder}, Delay, ExtendedColorType, Frame, RgbImage, RgbaImage};
+use log::info;
+
+/// 导出格式, 根据输出路径的扩展名选择, 没有扩展名时导出为PNG序列
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat{
+    Gif,
+    Apng,
+    Webp,
+    Mp4,
+    Webm,
+    PngSequence,
+}
+
+impl ExportFormat{
+    pub fn from_path(path: &Path) -> Result<Self>{
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref(){
+            Some("gif") => Ok(ExportFormat::Gif),
+            Some("png") | Some("apng") => Ok(ExportFormat::Apng),
+            Some("webp") => Ok(ExportFormat::Webp),
+            Some("mp4") => Ok(ExportFormat::Mp4),
+            Some("webm") => Ok(ExportFormat::Webm),
+            None => Ok(ExportFormat::PngSequence),
+            ext => Err(anyhow!("不支持的导出格式:{:?}", ext)),
+        }
+    }
+}
+
+/// 逐帧写入的编码器, 所有帧的尺寸必须一致
+pub trait FrameEncoder{
+    fn add_frame(&mut self, frame: &RgbImage) -> Result<()>;
+    /// 写入文件尾, 返回写入的帧数
+    fn finish(self: Box<Self>) -> Result<u32>;
+}
+
+/// 创建编码器, delay_ms为每帧显示时间, ffmpeg为ffmpeg可执行文件路径
+pub fn create_encoder(output: &Path, delay_ms: u32, ffmpeg: &str) -> Result<Box<dyn FrameEncoder>>{
+    Ok(match ExportFormat::from_path(output)?{
+        ExportFormat::Gif => Box::new(GifFrameEncoder::new(output, delay_ms)?),
+        ExportFormat::Apng => Box::new(ApngFrameEncoder::new(output, delay_ms)),
+        ExportFormat::Webp => Box::new(WebpFrameEncoder::new(output, delay_ms)),
+        ExportFormat::Mp4 => Box::new(FfmpegEncoder::new(output, delay_ms, ffmpeg, &["-c:v", "libx264", "-pix_fmt", "yuv420p", "-movflags", "+faststart"])),
+        ExportFormat::Webm => Box::new(FfmpegEncoder::new(output, delay_ms, ffmpeg, &["-c:v", "libvpx-vp9", "-pix_fmt", "yuv420p", "-b:v", "0", "-crf", "32"])),
+        ExportFormat::PngSequence => Box::new(PngSequenceEncoder::new(output)?),
+    })
+}
+
+/// GIF边编码边写入文件
+pub struct GifFrameEncoder{
+    encoder: GifEncoder<BufWriter<File>>,
+    delay: Delay,
+    count: u32,
+}
+
+impl GifFrameEncoder{
+    pub fn new(output: &Path, delay_ms: u32) -> Result<Self>{
+        let mut encoder = GifEncoder::new_with_speed(BufWriter::new(File::create(output)?), 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+        Ok(Self { encoder, delay: Delay::from_numer_denom_ms(delay_ms, 1), count: 0 })
+    }
+}
+
+impl FrameEncoder for GifFrameEncoder{
+    fn add_frame(&mut self, frame: &RgbImage) -> Result<()>{
+        let frame: RgbaImage = frame.convert();
+        self.encoder.encode_frame(Frame::from_parts(frame, 0, 0, self.delay))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<u32>{
+        // GifEncoder在drop时写入文件尾
+        Ok(self.count)
+    }
+}
+
+/// APNG的文件头需要总帧数, 所有帧先压缩成PNG追加到临时文件中, 结束时再逐帧读出写入APNG
+///
+/// 内存中同时只有一帧
+pub struct ApngFrameEncoder{
+    output: PathBuf,
+    delay_ms: u32,
+    size: Option<(u32, u32)>,
+    /// 临时文件, 每一帧为 4字节长度 + PNG数据
+    spool_path: PathBuf,
+    spool: Option<BufWriter<File>>,
+    count: u32,
+}
+
+impl ApngFrameEncoder{
+    pub fn new(output: &Path, delay_ms: u32) -> Self{
+        let mut spool_path = output.as_os_str().to_owned();
+        spool_path.push(".frames");
+        Self { output: output.to_path_buf(), delay_ms, size: None, spool_path: PathBuf::from(spool_path), spool: None, count: 0 }
+    }
+
+    fn write_apng(&self, width: u32, height: u32) -> Result<()>{
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(&self.output)?), width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.count, 0)?;
+        encoder.set_frame_delay(self.delay_ms.min(u16::MAX as u32) as u16, 1000)?;
+        let mut writer = encoder.write_header()?;
+        let mut spool = BufReader::new(File::open(&self.spool_path)?);
+        for _ in 0..self.count{
+            let mut len = [0; 4];
+            spool.read_exact(&mut len)?;
+            let mut data = vec![0; u32::from_le_bytes(len) as usize];
+            spool.read_exact(&mut data)?;
+            let frame = image::load_from_memory(&data)?.to_rgb8();
+            writer.write_image_data(frame.as_raw())?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+impl FrameEncoder for ApngFrameEncoder{
+    fn add_frame(&mut self, frame: &RgbImage) -> Result<()>{
+        self.size.get_or_insert(frame.dimensions());
+        let mut data = vec![];
+        frame.write_with_encoder(PngEncoder::new(&mut data))?;
+        let spool = match &mut self.spool{
+            Some(spool) => spool,
+            None => self.spool.insert(BufWriter::new(File::create(&self.spool_path)?)),
+        };
+        spool.write_all(&(data.len() as u32).to_le_bytes())?;
+        spool.write_all(&data)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<u32>{
+        let (width, height) = self.size.ok_or(anyhow!("没有可写入的帧"))?;
+        if let Some(mut spool) = self.spool.take(){
+            spool.flush()?;
+        }
+        let ret = self.write_apng(width, height);
+        let _ = fs::remove_file(&self.spool_path);
+        ret?;
+        Ok(self.count)
+    }
+}
+
+/// image只能编码单帧的WebP, 把每一帧的VP8L数据放进ANMF块中组成动画
+///
+/// 第一帧时写入文件头, 之后每一帧直接写入文件, 结束时回写RIFF大小
+pub struct WebpFrameEncoder{
+    output: PathBuf,
+    delay_ms: u32,
+    file: Option<BufWriter<File>>,
+    count: u32,
+}
+
+impl WebpFrameEncoder{
+    pub fn new(output: &Path, delay_ms: u32) -> Self{
+        Self { output: output.to_path_buf(), delay_ms, file: None, count: 0 }
+    }
+
+    /// RIFF头(大小先写0)、VP8X和ANIM
+    fn create(&self, width: u32, height: u32) -> Result<BufWriter<File>>{
+        let mut header = vec![];
+        // VP8X: 动画标志
+        let mut vp8x = vec![0x02, 0, 0, 0];
+        vp8x.extend_from_slice(&u24(width - 1));
+        vp8x.extend_from_slice(&u24(height - 1));
+        write_chunk(&mut header, b"VP8X", &vp8x);
+        // ANIM: 黑色背景, 无限循环
+        write_chunk(&mut header, b"ANIM", &[0, 0, 0, 255, 0, 0]);
+        let mut w = BufWriter::new(File::create(&self.output)?);
+        w.write_all(b"RIFF")?;
+        w.write_all(&0u32.to_le_bytes())?;
+        w.write_all(b"WEBP")?;
+        w.write_all(&header)?;
+        Ok(w)
+    }
+}
+
+impl FrameEncoder for WebpFrameEncoder{
+    fn add_frame(&mut self, frame: &RgbImage) -> Result<()>{
+        if self.file.is_none(){
+            self.file = Some(self.create(frame.width(), frame.height())?);
+        }
+        let mut single = vec![];
+        WebPEncoder::new_lossless(&mut single).encode(frame.as_raw(), frame.width(), frame.height(), ExtendedColorType::Rgb8)?;
+        let mut anmf = [u24(0), u24(0), u24(frame.width() - 1), u24(frame.height() - 1), u24(self.delay_ms)].concat();
+        // 不混合, 不清除
+        anmf.push(0x02);
+        anmf.extend_from_slice(image_chunks(&single)?);
+        let mut chunk = vec![];
+        write_chunk(&mut chunk, b"ANMF", &anmf);
+        if let Some(file) = &mut self.file{
+            file.write_all(&chunk)?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<u32>{
+        let mut file = self.file.ok_or(anyhow!("没有可写入的帧"))?;
+        // RIFF大小不包含开头的"RIFF"和大小字段
+        let len = file.stream_position()?;
+        file.seek(SeekFrom::Start(4))?;
+        file.write_all(&((len - 8) as u32).to_le_bytes())?;
+        file.flush()?;
+        Ok(self.count)
+    }
+}
+
+fn u24(v: u32) -> [u8; 3]{
+    let b = v.to_le_bytes();
+    [b[0], b[1], b[2]]
+}
+
+fn write_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]){
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1{
+        out.push(0);
+    }
+}
+
+/// 单帧WebP文件中从图像数据块(ALPH/VP8/VP8L)开始的部分
+fn image_chunks(webp: &[u8]) -> Result<&[u8]>{
+    let mut offset = 12;
+    while offset + 8 <= webp.len(){
+        let fourcc = &webp[offset..offset + 4];
+        if fourcc == b"ALPH" || fourcc == b"VP8 " || fourcc == b"VP8L"{
+            return Ok(&webp[offset..]);
+        }
+        let size = u32::from_le_bytes([webp[offset + 4], webp[offset + 5], webp[offset + 6], webp[offset + 7]]) as usize;
+        offset += 8 + size + size % 2;
+    }
+    Err(anyhow!("WebP数据格式错误"))
+}
+
+/// 每一帧保存为 输出目录/frame_00001.png
+pub struct PngSequenceEncoder{
+    dir: PathBuf,
+    count: u32,
+}
+
+impl PngSequenceEncoder{
+    pub fn new(dir: &Path) -> Result<Self>{
+        fs::create_dir_all(dir)?;
+        Ok(Self { dir: dir.to_path_buf(), count: 0 })
+    }
+}
+
+impl FrameEncoder for PngSequenceEncoder{
+    fn add_frame(&mut self, frame: &RgbImage) -> Result<()>{
+        self.count += 1;
+        frame.save(self.dir.join(format!("frame_{:05}.png", self.count)))?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<u32>{
+        Ok(self.count)
+    }
+}
+
+/// 通过管道把原始RGB帧写入ffmpeg, 由ffmpeg编码为视频
+///
+/// 收到第一帧后才知道尺寸, 那时再启动ffmpeg
+pub struct FfmpegEncoder{
+    output: PathBuf,
+    delay_ms: u32,
+    ffmpeg: String,
+    codec_args: Vec<String>,
+    process: Option<(Child, ChildStdin)>,
+    size: (u32, u32),
+    count: u32,
+}
+
+impl FfmpegEncoder{
+    pub fn new(output: &Path, delay_ms: u32, ffmpeg: &str, codec_args: &[&str]) -> Self{
+        Self {
+            output: output.to_path_buf(),
+            delay_ms: delay_ms.max(1),
+            ffmpeg: ffmpeg.to_string(),
+            codec_args: codec_args.iter().map(|s| s.to_string()).collect(),
+            process: None,
+            size: (0, 0),
+            count: 0,
+        }
+    }
+
+    fn spawn(&self, width: u32, height: u32) -> Result<(Child, ChildStdin)>{
+        let output = self.output.to_str().ok_or(anyhow!("输出路径错误:{:?}", self.output))?;
+        let mut child = Command::new(&self.ffmpeg)
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-s", &format!("{width}x{height}"), "-framerate", &format!("1000/{}", self.delay_ms)])
+            .args(["-i", "-"])
+            // yuv420p要求宽高为偶数
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+            .args(&self.codec_args)
+            .arg(output)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|err| anyhow!("ffmpeg启动失败, 请确认已安装ffmpeg:{:?}", err))?;
+        let stdin = child.stdin.take().ok_or(anyhow!("ffmpeg管道打开失败"))?;
+        info!("ffmpeg已启动 {width}x{height} {:?}", self.output);
+        Ok((child, stdin))
+    }
+}
+
+impl FrameEncoder for FfmpegEncoder{
+    fn add_frame(&mut self, frame: &RgbImage) -> Result<()>{
+        if self.process.is_none(){
+            self.size = frame.dimensions();
+            self.process = Some(self.spawn(frame.width(), frame.height())?);
+        }
+        if frame.dimensions() != self.size{
+            return Err(anyhow!("帧尺寸不一致: {:?} {:?}", frame.dimensions(), self.size));
+        }
+        if let Some((_, stdin)) = self.process.as_mut(){
+            stdin.write_all(frame.as_raw())?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<u32>{
+        let (mut child, stdin) = self.process.ok_or(anyhow!("没有可写入的帧"))?;
+        // 关闭管道, ffmpeg读到结尾后开始写文件尾
+        drop(stdin);
+        let status = child.wait()?;
+        if !status.success(){
+            return Err(anyhow!("ffmpeg编码失败:{status}"));
+        }
+        Ok(self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use image::{codecs::{png::PngDecoder, webp::WebPDecoder}, AnimationDecoder, Rgb};
+    use super::*;
+
+    /// 每帧颜色不同的测试帧, 奇数宽高用于检查VP8X和ANMF中的宽高和块的填充
+    fn frames() -> Vec<RgbImage>{
+        (0..3u8).map(|i| RgbImage::from_fn(37, 21, |x, y| Rgb([i * 80, (x * 6) as u8, (y * 12) as u8]))).collect()
+    }
+
+    fn encode(mut encoder: Box<dyn FrameEncoder>, frames: &[RgbImage]) -> u32{
+        for frame in frames{
+            encoder.add_frame(frame).unwrap();
+        }
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn webp_round_trip(){
+        let output = std::env::temp_dir().join(format!("encoder_test_{}.webp", std::process::id()));
+        let frames = frames();
+        assert_eq!(encode(Box::new(WebpFrameEncoder::new(&output, 150)), &frames), 3);
+        let data = fs::read(&output).unwrap();
+        let _ = fs::remove_file(&output);
+        // RIFF大小为文件长度减8
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize, data.len() - 8);
+        let decoder = WebPDecoder::new(std::io::Cursor::new(data)).unwrap();
+        assert!(decoder.has_animation());
+        let decoded = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), frames.len());
+        for (frame, expected) in decoded.iter().zip(&frames){
+            assert_eq!(frame.buffer().dimensions(), (37, 21));
+            assert_eq!(frame.delay().numer_denom_ms(), (150, 1));
+            // 无损编码, 像素完全相同
+            let rgb: RgbImage = frame.buffer().convert();
+            assert_eq!(&rgb, expected);
+        }
+    }
+
+    #[test]
+    fn apng_round_trip(){
+        let output = std::env::temp_dir().join(format!("encoder_test_{}.png", std::process::id()));
+        let frames = frames();
+        assert_eq!(encode(Box::new(ApngFrameEncoder::new(&output, 150)), &frames), 3);
+        // 临时文件已删除
+        assert!(!Path::new(&format!("{}.frames", output.display())).exists());
+        let data = fs::read(&output).unwrap();
+        let _ = fs::remove_file(&output);
+        let reader = png::Decoder::new(std::io::Cursor::new(&data)).read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (3, 0));
+        let decoded = PngDecoder::new(std::io::Cursor::new(&data)).unwrap().apng().unwrap().into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), frames.len());
+        for (frame, expected) in decoded.iter().zip(&frames){
+            assert_eq!(frame.delay().numer_denom_ms(), (150, 1));
+            let rgb: RgbImage = frame.buffer().convert();
+            assert_eq!(&rgb, expected);
+        }
+    }
+}
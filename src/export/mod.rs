@@ -0,0 +1,125 @@
+use std::{path::PathBuf, time::Duration};
+use anyhow::{anyhow, Result};
+use image::{buffer::ConvertBuffer, GenericImage, RgbImage};
+use log::{error, info};
+use time::PrimitiveDateTime;
+
+pub mod encoder;
+
+use crate::{config::Config, downloader::{fast_resize, fetcher::FetchOptions, source::{self, get_source, SatelliteSource, Visible}}, region::Region};
+use encoder::create_encoder;
+
+/// 延时动画导出参数
+#[derive(Clone, Debug)]
+pub struct ExportOptions{
+    /// 输出文件
+    pub output: PathBuf,
+    /// 卫星名字, 为空时使用配置中的卫星
+    pub satellite: String,
+    /// 导出最近几个小时
+    pub hours: u32,
+    /// 每帧间隔(分钟), 需要是卫星拍摄间隔的整数倍
+    pub interval: u32,
+    /// 网格大小
+    pub d: u32,
+    /// 输出宽度, 为0时不缩放
+    pub size: u32,
+    /// 裁剪区域
+    pub region: Option<Region>,
+    /// 每帧显示时间(毫秒)
+    pub frame_delay: u32,
+    /// 导出MP4/WebM时使用的ffmpeg
+    pub ffmpeg: String,
+}
+
+impl ExportOptions{
+    /// 解析命令行参数: /export out.gif hours=6 interval=30 satellite=h8 d=2 size=800 delay=200
+    ///
+    /// 输出支持gif/png/webp动画, mp4/webm视频(需要ffmpeg), 没有扩展名时导出为PNG序列
+    ///
+    /// 区域使用配置文件中的region_name或region_bbox
+    pub fn parse(args: &[String], cfg: &Config) -> Result<Self>{
+        let output = args.first().ok_or(anyhow!("缺少输出文件, 例如: /export earth.gif hours=6"))?;
+        let mut options = ExportOptions{
+            output: PathBuf::from(output),
+            satellite: cfg.satellite_name.clone(),
+            hours: 24,
+            interval: 30,
+            d: 2,
+            size: 800,
+            region: Region::from_config(cfg),
+            frame_delay: 200,
+            ffmpeg: String::from("ffmpeg"),
+        };
+        for arg in &args[1..]{
+            let (key, value) = arg.split_once('=').ok_or(anyhow!("参数格式错误:{arg}"))?;
+            match key{
+                "satellite" => options.satellite = value.to_string(),
+                "hours" => options.hours = value.parse()?,
+                "interval" => options.interval = value.parse()?,
+                "d" => options.d = value.parse()?,
+                "size" => options.size = value.parse()?,
+                "delay" => options.frame_delay = value.parse()?,
+                "ffmpeg" => options.ffmpeg = value.to_string(),
+                _ => return Err(anyhow!("未知参数:{key}")),
+            }
+        }
+        if options.interval == 0{
+            return Err(anyhow!("interval不能为0"));
+        }
+        Ok(options)
+    }
+}
+
+/// 从最新的图片往前, 每隔interval分钟取一帧, 按时间从旧到新排列
+pub fn frame_times(latest: PrimitiveDateTime, hours: u32, interval: u32) -> Vec<PrimitiveDateTime>{
+    let count = (hours * 60 / interval).max(1);
+    (0..count).rev().map(|i| latest - Duration::from_secs(60 * interval as u64 * i as u64)).collect()
+}
+
+/// 下载一帧, 裁剪并缩放
+pub fn render_frame(source: &dyn SatelliteSource, cfg: &Config, options: &ExportOptions, time: PrimitiveDateTime) -> Result<RgbImage>{
+    let d = source.grid_size(options.d);
+    let image = source::download_part(source, source.base_url(cfg), d, time, Visible::ALL, &FetchOptions::from_config(cfg), |_, _| {})?;
+    let mut image: RgbImage = image.image.convert();
+    if let Some(region) = options.region{
+        let (x, y, w, h) = region.rect_on_disk(source.disk(), image.width(), image.height())
+            .ok_or(anyhow!("区域不在卫星视野内: {:?}", region))?;
+        image = image.sub_image(x, y, w, h).to_image();
+    }
+    if options.size > 0 && options.size != image.width(){
+        let height = (image.height() as u64 * options.size as u64 / image.width() as u64) as u32;
+        image = fast_resize(&image, options.size, height.max(1));
+    }
+    Ok(image)
+}
+
+/// 导出延时动画, callback参数为(已完成帧数, 总帧数)
+///
+/// 每下载一帧就交给编码器, 不在内存中保存所有帧
+pub fn export_animation<C: Fn(u32, u32)>(cfg: &Config, options: &ExportOptions, callback: C) -> Result<()>{
+    let mut encoder = create_encoder(&options.output, options.frame_delay, &options.ffmpeg)?;
+    let source = get_source(&options.satellite);
+    let latest = source.latest_time(cfg)?;
+    let times = frame_times(latest, options.hours, options.interval);
+    info!("导出动画 {:?} {} 共{}帧", options.output, source.name(), times.len());
+
+    for (i, time) in times.iter().enumerate(){
+        match render_frame(source, cfg, options, *time){
+            Ok(frame) => encoder.add_frame(&frame)?,
+            //缺少的帧跳过
+            Err(err) => error!("{time} 下载失败, 跳过该帧:{:?}", err),
+        }
+        callback(i as u32 + 1, times.len() as u32);
+    }
+    let count = encoder.finish().map_err(|err| anyhow!("没有下载到任何图片 {:?}", err))?;
+    info!("动画导出完成:{:?} 共{count}帧", options.output);
+    Ok(())
+}
+
+/// 命令行导出: /export out.gif hours=6 ...
+pub fn run_export_command(args: &[String]) -> Result<()>{
+    let cfg = async_std::task::block_on(Config::load_or_default());
+    let options = ExportOptions::parse(args, &cfg)?;
+    export_animation(&cfg, &options, |i, t| info!("正在导出: {i}/{t}"))
+}
@@ -0,0 +1,245 @@
+use std::{fs, path::{Path, PathBuf}, time::{Duration, SystemTime}};
+use anyhow::{anyhow, Result};
+use image::RgbImage;
+use log::{info, warn};
+
+use crate::{app::{get_app_home_dir, get_wallpaper_file_path, set_lock_screen_image, set_wallpaper_from_path}, config::Config, downloader::source::utc_time};
+
+/// 壁纸历史, 每次生成的壁纸按 卫星/时间.png 保存在app目录的history文件夹下
+#[derive(Clone, Debug)]
+pub struct WallpaperHistory{
+    dir: PathBuf,
+    /// 最多保存的壁纸数量
+    max_count: usize,
+    /// 保留时间, None为不按时间清理
+    max_age: Option<Duration>,
+    /// 总大小上限(字节)
+    max_bytes: u64,
+}
+
+/// 一张历史壁纸
+#[derive(Clone, Debug)]
+pub struct HistoryEntry{
+    pub path: PathBuf,
+    /// 卫星名字
+    pub satellite: String,
+    /// 图片时间, 和Config.current_wallpaper_date格式相同
+    pub timestr: String,
+    /// 文件大小(字节)
+    pub size: u64,
+    /// 保存时间
+    pub modified: SystemTime,
+}
+
+impl Default for WallpaperHistory{
+    fn default() -> Self {
+        Self {
+            dir: Path::new(&get_app_home_dir()).join("history"),
+            max_count: 200,
+            max_age: Some(Duration::from_secs(30 * 24 * 3600)),
+            max_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl WallpaperHistory{
+    /// 保存数量设为0时不保存历史壁纸, 保留天数设为0时不按时间清理
+    pub fn from_config(cfg: &Config) -> Option<Self>{
+        if cfg.history_max_count == 0{
+            return None;
+        }
+        Some(Self {
+            max_count: cfg.history_max_count as usize,
+            max_age: max_age_days(cfg.history_days),
+            max_bytes: cfg.history_size_mb as u64 * 1024 * 1024,
+            ..Default::default()
+        })
+    }
+
+    fn path(&self, satellite: &str, timestr: &str) -> PathBuf{
+        self.dir.join(satellite).join(format!("{timestr}.png"))
+    }
+
+    /// 保存一张壁纸, 然后按保留策略清理
+    pub fn archive(&self, satellite: &str, timestr: &str, paper: &RgbImage) -> Result<PathBuf>{
+        let path = self.path(satellite, timestr);
+        if let Some(dir) = path.parent(){
+            fs::create_dir_all(dir)?;
+        }
+        paper.save(&path)?;
+        info!("壁纸已存档:{:?}", path);
+        self.apply_retention()?;
+        Ok(path)
+    }
+
+    /// 所有历史壁纸, 最新的排在前面
+    pub fn list(&self) -> Result<Vec<HistoryEntry>>{
+        let mut entries = vec![];
+        if !self.dir.exists(){
+            return Ok(entries);
+        }
+        for satellite_dir in fs::read_dir(&self.dir)?{
+            let satellite_dir = satellite_dir?;
+            if !satellite_dir.file_type()?.is_dir(){
+                continue;
+            }
+            let satellite = satellite_dir.file_name().to_string_lossy().to_string();
+            for file in fs::read_dir(satellite_dir.path())?{
+                let file = file?;
+                let path = file.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("png"){
+                    continue;
+                }
+                let meta = file.metadata()?;
+                entries.push(HistoryEntry{
+                    timestr: path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+                    satellite: satellite.clone(),
+                    size: meta.len(),
+                    modified: meta.modified()?,
+                    path,
+                });
+            }
+        }
+        entries.sort_by_key(|e| std::cmp::Reverse(e.modified));
+        Ok(entries)
+    }
+
+    /// 把历史壁纸重新设置为桌面壁纸, 并更新配置中的当前壁纸
+    ///
+    /// 同时把卫星和固定壁纸时间设为该壁纸的, 避免定时更新用最新图片覆盖
+    pub fn restore(&self, entry: &HistoryEntry, cfg: &mut Config) -> Result<()>{
+        self.check_path(&entry.path)?;
+        let pinned_time = pinned_time(&entry.timestr).ok_or(anyhow!("无法识别历史壁纸时间:{}", entry.timestr))?;
+        let wallpaper_file_path = get_wallpaper_file_path();
+        fs::copy(&entry.path, &wallpaper_file_path)?;
+        info!("恢复历史壁纸:{:?}", entry.path);
+        let loc_res = set_lock_screen_image(&wallpaper_file_path);
+        info!("锁屏设置结果: {:?}", loc_res);
+        set_wallpaper_from_path(&wallpaper_file_path)?;
+        cfg.current_wallpaper_file = wallpaper_file_path;
+        cfg.current_wallpaper_date = entry.timestr.clone();
+        cfg.satellite_name = entry.satellite.clone();
+        cfg.pinned_time = pinned_time;
+        Ok(())
+    }
+
+    /// 复制一张历史壁纸到app目录的export文件夹, 返回复制后的路径
+    pub fn export(&self, entry: &HistoryEntry) -> Result<PathBuf>{
+        self.check_path(&entry.path)?;
+        let dir = Path::new(&get_app_home_dir()).join("export");
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.png", entry.timestr));
+        fs::copy(&entry.path, &path)?;
+        info!("导出历史壁纸:{:?}", path);
+        Ok(path)
+    }
+
+    /// 按路径查找历史壁纸
+    pub fn find(&self, path: &str) -> Result<HistoryEntry>{
+        self.list()?.into_iter().find(|e| e.path == Path::new(path)).ok_or(anyhow!("历史壁纸不存在:{path}"))
+    }
+
+    /// 删除一张历史壁纸
+    pub fn delete(&self, entry: &HistoryEntry) -> Result<()>{
+        self.check_path(&entry.path)?;
+        fs::remove_file(&entry.path)?;
+        info!("删除历史壁纸:{:?}", entry.path);
+        if let Some(dir) = entry.path.parent(){
+            // 非空文件夹删除会失败
+            let _ = fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+
+    /// 只允许操作history文件夹中的文件
+    fn check_path(&self, path: &Path) -> Result<()>{
+        if !path.starts_with(&self.dir){
+            return Err(anyhow!("不是历史壁纸文件:{:?}", path));
+        }
+        Ok(())
+    }
+
+    /// 删除过期的壁纸, 数量或总大小超出上限时从最旧的开始删除
+    pub fn apply_retention(&self) -> Result<()>{
+        let now = SystemTime::now();
+        let mut keep = vec![];
+        for entry in self.list()?{
+            if self.max_age.is_some_and(|max_age| now.duration_since(entry.modified).unwrap_or_default() > max_age){
+                self.remove(&entry);
+            }else{
+                keep.push(entry);
+            }
+        }
+        // list()中最新的在前, 从后面删除
+        let mut total: u64 = keep.iter().map(|e| e.size).sum();
+        while keep.len() > self.max_count || (total > self.max_bytes && keep.len() > 1){
+            let Some(entry) = keep.pop() else { break };
+            total -= entry.size;
+            self.remove(&entry);
+        }
+        info!("历史壁纸清理完成, 共{}张 {}KB", keep.len(), total / 1024);
+        Ok(())
+    }
+
+    fn remove(&self, entry: &HistoryEntry){
+        if let Err(err) = self.delete(entry){
+            warn!("历史壁纸删除失败:{:?} {:?}", entry.path, err);
+        }
+    }
+}
+
+/// 保留天数转为保留时间, 0为不按时间清理
+pub fn max_age_days(days: u32) -> Option<Duration>{
+    if days == 0{
+        return None;
+    }
+    Some(Duration::from_secs(days as u64 * 24 * 3600))
+}
+
+/// 由壁纸时间(format_time_str生成, 如"h8-D2-UTC-2024年-10月-29日-12时-10分")得到固定壁纸时间
+///
+/// 壁纸时间是图片的准确时间, 固定为该时间后重新下载得到的是同一张图片
+fn pinned_time(timestr: &str) -> Option<String>{
+    let mut parts = timestr.rsplitn(6, '-');
+    let mut number = |unit: char| parts.next()?.strip_suffix(unit)?.parse::<u32>().ok();
+    let (minute, hour, day, month, year) = (number('分')?, number('时')?, number('日')?, number('月')?, number('年')?);
+    let time = utc_time(year as i32, month as u8, day as u8, hour as u8, minute as u8).ok()?;
+    Some(format!("{}-{:02}-{:02} {:02}:{:02}", time.year(), time.month() as u8, time.day(), time.hour(), time.minute()))
+}
+
+#[cfg(test)]
+mod tests{
+    use time::macros::datetime;
+    use crate::downloader::{format_time_str, h8::H8, source::{parse_pinned_time, SatelliteSource}};
+    use super::*;
+
+    #[test]
+    fn zero_days_means_no_age_limit(){
+        assert_eq!(max_age_days(0), None);
+        assert_eq!(max_age_days(2), Some(Duration::from_secs(2 * 24 * 3600)));
+    }
+
+    #[test]
+    fn pinned_time_from_timestr(){
+        assert_eq!(pinned_time("fy4b-D2-UTC-2024年-10月-29日-12时-15分").as_deref(), Some("2024-10-29 12:15"));
+        assert_eq!(pinned_time("goes_east-D1-UTC-2024年-12月-31日-23时-50分").as_deref(), Some("2024-12-31 23:50"));
+        assert_eq!(pinned_time("wallpaper"), None);
+    }
+
+    #[test]
+    fn ten_minute_slots_stay_distinct(){
+        // 向日葵8号每10分钟一张, 12:00和12:10是两张不同的壁纸
+        let times = [datetime!(2024-10-29 12:00), datetime!(2024-10-29 12:10)];
+        let timestrs: Vec<String> = times.iter()
+            .map(|t| format_time_str(H8.name(), 2, t.year(), t.month() as u8, t.day(), t.hour(), t.minute()))
+            .collect();
+        assert_ne!(timestrs[0], timestrs[1]);
+        let history = WallpaperHistory{ dir: PathBuf::from("history"), ..Default::default() };
+        assert_ne!(history.path(H8.name(), &timestrs[0]), history.path(H8.name(), &timestrs[1]));
+        // 恢复时固定的时间对应同一张图片
+        for (time, timestr) in times.iter().zip(&timestrs){
+            let pinned = parse_pinned_time(&pinned_time(timestr).unwrap()).unwrap();
+            assert_eq!(H8.pinned_timestamps(pinned)[0], *time);
+        }
+    }
+}
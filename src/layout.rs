@@ -0,0 +1,565 @@
+use std::time::Instant;
+use anyhow::{anyhow, Result};
+use chrono::{NaiveTime, Timelike};
+use image::{GenericImage, GenericImageView, Rgb, RgbImage, RgbaImage};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{background::{self, BackgroundStyle}, downloader::{fast_resize_rgba, source::{ImagePart, Visible}}, projection::{limb_distance, Disk}, region::{fit_aspect, Region}};
+
+/// 图片中的矩形范围(x, y, 宽, 高)
+type Rect = (u32, u32, u32, u32);
+
+/// 竖屏显示半个地球时多取一点, 避免正好切在中线上
+const PORTRAIT_HALF_WIDTH: f64 = 0.5 * 1.06;
+
+/// 水平位置
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HAnchor{
+    Left,
+    Center,
+    Right,
+}
+
+impl HAnchor{
+    /// 剩余空间为free时的偏移
+    fn offset(&self, free: i64) -> i64{
+        match self{
+            HAnchor::Left => 0,
+            HAnchor::Center => free / 2,
+            HAnchor::Right => free,
+        }
+    }
+}
+
+/// 垂直位置
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VAnchor{
+    Top,
+    Center,
+    Bottom,
+}
+
+impl VAnchor{
+    /// 剩余空间为free时的偏移
+    fn offset(&self, free: i64) -> i64{
+        match self{
+            VAnchor::Top => 0,
+            VAnchor::Center => free / 2,
+            VAnchor::Bottom => free,
+        }
+    }
+}
+
+/// 竖屏半张显示时显示地球的哪一半
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortraitSide{
+    /// 上午显示东半边, 下午显示西半边
+    ByTime,
+    /// 总是显示东半边(图片右半部分), 放在屏幕左边
+    East,
+    /// 总是显示西半边(图片左半部分), 放在屏幕右边
+    West,
+}
+
+/// 布局配置, 对应配置文件中的[layout]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig{
+    /// 整张显示时地球直径占屏幕高度的百分比
+    pub scale: u32,
+
+    /// 半张显示时地球直径占屏幕宽度的百分比
+    pub half_scale: u32,
+
+    /// 横屏半张显示时地球顶部离屏幕顶部的距离(屏幕高度的百分比)
+    pub half_top: u32,
+
+    /// 水平位置 left、center、right, 竖屏半张显示时由portrait_side决定
+    pub horizontal: HAnchor,
+
+    /// 垂直位置 top、center、bottom, 横屏半张显示时由half_top决定
+    pub vertical: VAnchor,
+
+    /// 屏幕四周的留白(像素)
+    pub padding: u32,
+
+    /// 背景样式 color、starfield、blur、image
+    pub background_style: BackgroundStyle,
+
+    /// 背景颜色, 格式"#RRGGBB", 星空背景也以此为底色
+    pub background: String,
+
+    /// 渐变背景底部的颜色, 为空时为纯色背景
+    pub background_gradient: String,
+
+    /// 星空背景的随机种子, 种子不变时每次生成的星空相同
+    pub starfield_seed: u32,
+
+    /// 自定义背景图片路径, 等比缩放铺满屏幕
+    pub background_image: String,
+
+    /// 竖屏半张显示时显示地球的哪一半 by_time、east、west
+    pub portrait_side: PortraitSide,
+}
+
+impl Default for LayoutConfig{
+    fn default() -> Self {
+        Self {
+            scale: 90,
+            half_scale: 95,
+            half_top: 5,
+            horizontal: HAnchor::Center,
+            vertical: VAnchor::Center,
+            padding: 0,
+            background_style: BackgroundStyle::Color,
+            background: String::from("#000000"),
+            background_gradient: String::new(),
+            starfield_seed: 1,
+            background_image: String::new(),
+            portrait_side: PortraitSide::ByTime,
+        }
+    }
+}
+
+impl LayoutConfig{
+    /// 竖屏半张显示时是否显示地球的东半边(图片右半部分)
+    fn show_east(&self, now: NaiveTime) -> bool{
+        match self.portrait_side{
+            PortraitSide::ByTime => now.hour() <= 12,
+            PortraitSide::East => true,
+            PortraitSide::West => false,
+        }
+    }
+
+    /// 去掉留白后的显示区域 (留白, 宽, 高)
+    fn area(&self, screen: (u32, u32)) -> (u32, u32, u32){
+        let (width, height) = screen;
+        let padding = self.padding.min(width.min(height).saturating_sub(1) / 2);
+        (padding, width - padding * 2, height - padding * 2)
+    }
+}
+
+/// 壁纸布局参数
+#[derive(Clone, Debug)]
+pub struct LayoutOptions{
+    /// 半张显示: 横屏时只显示地球上半部分, 竖屏时只显示左半边或右半边
+    pub half: bool,
+    /// 裁剪区域, 优先于整张/半张布局
+    pub region: Option<Region>,
+    /// 全圆盘图的星下点和投影, 全球拼接图为None
+    pub disk: Option<Disk>,
+    pub layout: LayoutConfig,
+}
+
+/// 百分比转换为比例
+fn percent(v: u32) -> f64{
+    v.max(1) as f64 / 100.0
+}
+
+/// 图片在壁纸上的位置
+struct Placement{
+    /// 使用的完整图片部分
+    rect: Rect,
+    /// 缩放后的大小
+    size: (u32, u32),
+    /// 缩放后在显示区域中的位置
+    x: i64,
+    y: i64,
+}
+
+/// 按布局计算完整图片(full大小)在显示区域(area大小)中的位置, 和visible、diameter对应
+fn placement(full: (u32, u32), area: (u32, u32), options: &LayoutOptions, now: NaiveTime) -> Result<Placement>{
+    let layout = &options.layout;
+    let (width, height) = area;
+    let (full_width, full_height) = full;
+    let (rect, size, x, y) = if let Some(region) = options.region{
+        //裁剪区域并铺满屏幕
+        let rect = match options.disk{
+            Some(disk) => region.rect_on_disk(disk, full_width, full_height),
+            None => region.rect_on_map(full_width, full_height),
+        }.ok_or(anyhow!("区域不在卫星视野内: {:?}", region))?;
+        let (x, y, w, h) = fit_aspect(rect, width as f64 / height as f64, full_width, full_height);
+        info!("compose>>裁剪区域 {:?} => {x},{y} {w}x{h}", region);
+        let scale = (width as f64 / w as f64).min(height as f64 / h as f64);
+        let size = fit_size(w as f64 * scale, h as f64 * scale, width, height);
+        let (px, py) = anchor_position((width, height), size, layout);
+        ((x, y, w, h), size, px, py)
+    }else if height < width || !options.half{
+        //横屏模式, 图片稍微缩小一点
+        let scale = if !options.half{
+            (height as f64 * percent(layout.scale)) / full_height as f64
+        }else{
+            (width as f64 * percent(layout.half_scale)) / full_width as f64
+        };
+        let size = fit_size(full_width as f64 * scale, full_height as f64 * scale, width, height);
+        let (px, py) = if options.half{
+            //从屏幕高度half_top处开始显示上半块, 超出屏幕底部的部分不显示
+            let x = layout.horizontal.offset(width as i64 - size.0 as i64);
+            let y = (height as f64 * layout.half_top as f64 / 100.0) as i64;
+            (x, y)
+        }else{
+            anchor_position((width, height), size, layout)
+        };
+        ((0, 0, full_width, full_height), size, px, py)
+    }else{
+        //竖屏: 东半边显示在屏幕左边，西半边显示在屏幕右边
+        let east = layout.show_east(now);
+        let w = ((full_width as f64 * PORTRAIT_HALF_WIDTH) as u32).min(full_width);
+        let x = if east { full_width - w } else { 0 };
+        //缩放，最大不超过屏幕大小
+        let scale = percent(layout.half_scale);
+        let size = fit_size(w as f64 * scale, full_height as f64 * scale, width, height);
+        let px = if east { 0 } else { width as i64 - size.0 as i64 };
+        let py = layout.vertical.offset(height as i64 - size.1 as i64);
+        ((x, 0, w, full_height), size, px, py)
+    };
+    Ok(Placement{ rect, size, x, y })
+}
+
+/// 把卫星图片按布局画到screen大小的背景上, now为本地时间
+///
+/// image可以只是完整图片的一部分(只下载了可见瓦片), 布局按完整图片计算
+///
+/// 图片中透明的部分(未下载的瓦片)和全圆盘图中地球以外的部分显示背景
+pub fn compose(image: &ImagePart, screen: (u32, u32), options: &LayoutOptions, now: NaiveTime) -> Result<RgbImage>{
+    let layout = &options.layout;
+    let t = Instant::now();
+    let mut paper = background::render(screen, layout, &image.image);
+    info!("compose>>背景 {:?} 耗时:{}ms", layout.background_style, t.elapsed().as_millis());
+    //图片只画在留白以内, 超出的部分不显示
+    let (padding, width, height) = layout.area(screen);
+    let Placement{ rect, size, x, y } = placement((image.width, image.height), (width, height), options, now)?;
+    //只缩放已下载的部分, 其余部分显示背景
+    if let Some((mut globe, part_rect, (ox, oy))) = render_rect(image, rect, size){
+        if let Some(disk) = options.disk{
+            mask_limb(&mut globe, part_rect, disk, (image.width, image.height));
+        }
+        blend(&mut *paper.sub_image(padding, padding, width, height), &globe, x + ox, y + oy);
+    }
+    Ok(paper)
+}
+
+/// 完整图片(full大小)中的像素(px, py)在compose生成的壁纸上的位置, 不在显示范围内时返回None
+pub fn paper_position(full: (u32, u32), screen: (u32, u32), options: &LayoutOptions, now: NaiveTime, px: f64, py: f64) -> Option<(f64, f64)>{
+    let (padding, width, height) = options.layout.area(screen);
+    let Placement{ rect, size, x, y } = placement(full, (width, height), options, now).ok()?;
+    let (rx, ry, rw, rh) = (rect.0 as f64, rect.1 as f64, rect.2 as f64, rect.3 as f64);
+    if px < rx || py < ry || px > rx + rw || py > ry + rh{
+        return None;
+    }
+    let sx = padding as f64 + x as f64 + (px - rx) * size.0 as f64 / rw;
+    let sy = padding as f64 + y as f64 + (py - ry) * size.1 as f64 / rh;
+    let (right, bottom) = ((padding + width) as f64, (padding + height) as f64);
+    if sx < padding as f64 || sy < padding as f64 || sx >= right || sy >= bottom{
+        return None;
+    }
+    Some((sx, sy))
+}
+
+/// 等比缩放到w x h后的大小, 不超过max_width x max_height
+fn fit_size(w: f64, h: f64, max_width: u32, max_height: u32) -> (u32, u32){
+    let s = (max_width as f64 / w).min(max_height as f64 / h).min(1.0);
+    (((w * s) as u32).clamp(1, max_width), ((h * s) as u32).clamp(1, max_height))
+}
+
+/// 把完整图片中rect部分缩放到size大小, 只处理image中实际有的部分
+///
+/// 返回缩放后的图片、它对应的完整图片范围和在size中的偏移, 和rect不相交时返回None
+fn render_rect(image: &ImagePart, rect: Rect, size: (u32, u32)) -> Option<(RgbaImage, Rect, (i64, i64))>{
+    let part_rect = image.intersect(rect)?;
+    let (x, y, w, h) = part_rect;
+    let (sx, sy) = (size.0 as f64 / rect.2 as f64, size.1 as f64 / rect.3 as f64);
+    // 起点和终点分别取整, 相邻部分之间没有缝隙
+    let x0 = ((x - rect.0) as f64 * sx) as i64;
+    let y0 = ((y - rect.1) as f64 * sy) as i64;
+    let x1 = ((x + w - rect.0) as f64 * sx) as i64;
+    let y1 = ((y + h - rect.1) as f64 * sy) as i64;
+    let (dw, dh) = ((x1 - x0).max(1) as u32, (y1 - y0).max(1) as u32);
+    let part = image.image.view(x - image.x, y - image.y, w, h);
+    let globe = if (dw, dh) == (w, h){
+        part.to_image()
+    }else{
+        let t = Instant::now();
+        let resized = fast_resize_rgba(&part.to_image(), dw, dh);
+        info!("compose>>图片缩放 {w}x{h} => {dw}x{dh} 耗时:{}ms", t.elapsed().as_millis());
+        resized
+    };
+    Some((globe, part_rect, (x0, y0)))
+}
+
+/// 按配置的水平和垂直位置计算size大小的图片在area中的位置
+fn anchor_position(area: (u32, u32), size: (u32, u32), layout: &LayoutConfig) -> (i64, i64){
+    let x = layout.horizontal.offset(area.0 as i64 - size.0 as i64);
+    let y = layout.vertical.offset(area.1 as i64 - size.1 as i64);
+    (x, y)
+}
+
+/// 把地球以外的部分设为透明, 边缘按覆盖比例抗锯齿
+///
+/// image为size大小的全圆盘图中rect部分缩放后的图片
+fn mask_limb(image: &mut RgbaImage, rect: (u32, u32, u32, u32), disk: Disk, size: (u32, u32)){
+    let (x0, y0, w, h) = rect;
+    let (sx, sy) = (w as f64 / image.width() as f64, h as f64 / image.height() as f64);
+    // 原图的距离换算为缩放后的像素
+    let scale = 1.0 / sx.max(sy);
+    for (x, y, pixel) in image.enumerate_pixels_mut(){
+        let px = x0 as f64 + (x as f64 + 0.5) * sx;
+        let py = y0 as f64 + (y as f64 + 0.5) * sy;
+        let coverage = limb_distance(px, py, disk.projection, size.0, size.1) * scale + 0.5;
+        if coverage < 1.0{
+            pixel[3] = (pixel[3] as f64 * coverage.max(0.0)).round() as u8;
+        }
+    }
+}
+
+/// 按透明度把图片叠加到(x, y)处, 超出背景的部分不显示
+fn blend<I: GenericImage<Pixel = Rgb<u8>>>(paper: &mut I, image: &RgbaImage, x: i64, y: i64){
+    let (width, height) = (paper.width() as i64, paper.height() as i64);
+    for (ix, iy, pixel) in image.enumerate_pixels(){
+        let (px, py) = (x + ix as i64, y + iy as i64);
+        let a = pixel[3] as u32;
+        if a == 0 || px < 0 || py < 0 || px >= width || py >= height{
+            continue;
+        }
+        let mut bg = paper.get_pixel(px as u32, py as u32);
+        for c in 0..3{
+            bg[c] = ((pixel[c] as u32 * a + bg[c] as u32 * (255 - a) + 127) / 255) as u8;
+        }
+        paper.put_pixel(px as u32, py as u32, bg);
+    }
+}
+
+/// 布局中会显示的图片部分, 和compose中的布局对应, 用于只下载需要的瓦片
+pub fn visible(screen: (u32, u32), options: &LayoutOptions, now: NaiveTime) -> Visible{
+    let layout = &options.layout;
+    let (_, width, height) = layout.area(screen);
+    if let (Some(region), Some(disk)) = (options.region, options.disk){
+        // 在一张虚拟的全圆盘图上计算, 结果和图片大小无关
+        let size = 10000;
+        return match region.rect_on_disk(disk, size, size){
+            Some(rect) => {
+                let (x, y, w, h) = fit_aspect(rect, width as f64 / height as f64, size, size);
+                let size = size as f64;
+                Visible{ left: x as f64 / size, top: y as f64 / size, right: (x + w) as f64 / size, bottom: (y + h) as f64 / size }
+            }
+            None => Visible::ALL,
+        };
+    }
+    if !options.half{
+        return Visible::ALL;
+    }
+    if height < width{
+        //半张: 图片不超过屏幕大小, 从屏幕高度half_top处开始显示到屏幕底部
+        let size = (width as f64 * percent(layout.half_scale)).min(height as f64);
+        let ch = height as f64 - (height as f64 * layout.half_top as f64 / 100.0).floor();
+        Visible{ bottom: (ch / size).clamp(0.0, 1.0), ..Visible::ALL }
+    }else if layout.show_east(now){
+        Visible{ left: 1.0 - PORTRAIT_HALF_WIDTH, ..Visible::ALL }
+    }else{
+        Visible{ right: PORTRAIT_HALF_WIDTH, ..Visible::ALL }
+    }
+}
+
+/// 布局中地球在屏幕上的最大直径(像素), 和compose中的布局对应, 用于选择网格大小
+pub fn diameter(screen: (u32, u32), options: &LayoutOptions, now: NaiveTime) -> f64{
+    let layout = &options.layout;
+    let (_, width, height) = layout.area(screen);
+    let (width, height) = (width as f64, height as f64);
+    if options.region.is_some() && options.disk.is_some(){
+        let v = visible(screen, options, now);
+        return (width / (v.right - v.left)).max(height / (v.bottom - v.top));
+    }
+    if !options.half{
+        (height * percent(layout.scale)).min(width)
+    }else if height < width{
+        (width * percent(layout.half_scale)).min(height)
+    }else{
+        //竖屏显示半个地球
+        height.min(width / PORTRAIT_HALF_WIDTH)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use image::Rgba;
+    use super::*;
+
+    const MORNING: NaiveTime = match NaiveTime::from_hms_opt(9, 0, 0){ Some(t) => t, None => NaiveTime::MIN };
+
+    /// 红色的合成全圆盘图, 地球以外的部分由mask_limb去掉
+    fn disk_image(size: u32) -> ImagePart{
+        ImagePart::full(RgbaImage::from_pixel(size, size, Rgba([255, 0, 0, 255])))
+    }
+
+    fn options(half: bool, layout: LayoutConfig) -> LayoutOptions{
+        LayoutOptions{ half, region: None, disk: Some(Disk::geostationary(140.7)), layout }
+    }
+
+    /// 地球在壁纸上的范围(x0, y0, x1, y1), 包含x1、y1
+    fn globe_bounds(paper: &RgbImage) -> (u32, u32, u32, u32){
+        let mut bounds = (u32::MAX, u32::MAX, 0, 0);
+        for (x, y, pixel) in paper.enumerate_pixels(){
+            if pixel[0] > 128{
+                bounds = (bounds.0.min(x), bounds.1.min(y), bounds.2.max(x), bounds.3.max(y));
+            }
+        }
+        assert!(bounds.0 <= bounds.2, "壁纸上没有地球");
+        bounds
+    }
+
+    fn is_background(paper: &RgbImage, x: u32, y: u32) -> bool{
+        *paper.get_pixel(x, y) == Rgb([0, 0, 0])
+    }
+
+    fn assert_near(value: u32, expected: u32, tolerance: u32){
+        assert!(value.abs_diff(expected) <= tolerance, "{value} != {expected}±{tolerance}");
+    }
+
+    #[test]
+    fn landscape_globe_is_centred(){
+        let options = options(false, LayoutConfig::default());
+        let paper = compose(&disk_image(1100), (1920, 1080), &options, MORNING).unwrap();
+        assert_eq!(paper.dimensions(), (1920, 1080));
+        let (x0, y0, x1, y1) = globe_bounds(&paper);
+        // 直径为屏幕高度的90%
+        assert_eq!(diameter((1920, 1080), &options, MORNING), 972.0);
+        assert_near(x1 - x0 + 1, 972, 4);
+        assert_near(y1 - y0 + 1, 972, 4);
+        assert_near((x0 + x1) / 2, 960, 2);
+        assert_near((y0 + y1) / 2, 540, 2);
+        for (x, y) in [(0, 0), (1919, 1079), (x0 - 2, 540), (x1 + 2, 540), (960, y0 - 2), (960, y1 + 2), (x0 + 20, y0 + 20)]{
+            assert!(is_background(&paper, x, y), "({x}, {y})");
+        }
+        assert_eq!(visible((1920, 1080), &options, MORNING), Visible::ALL);
+    }
+
+    #[test]
+    fn portrait_globe_fits_width(){
+        let options = options(false, LayoutConfig::default());
+        let paper = compose(&disk_image(1100), (1080, 1920), &options, MORNING).unwrap();
+        assert_eq!(paper.dimensions(), (1080, 1920));
+        let (x0, y0, x1, y1) = globe_bounds(&paper);
+        // 90%的屏幕高度超出了宽度, 以宽度为准
+        assert_eq!(diameter((1080, 1920), &options, MORNING), 1080.0);
+        assert_near(x0, 0, 2);
+        assert_near(x1, 1079, 2);
+        assert_near((y0 + y1) / 2, 960, 2);
+        assert!(is_background(&paper, 540, y0 - 2));
+        assert!(is_background(&paper, 540, y1 + 2));
+    }
+
+    #[test]
+    fn ultrawide_globe_is_centred(){
+        let options = options(false, LayoutConfig::default());
+        let paper = compose(&disk_image(1100), (5120, 1440), &options, MORNING).unwrap();
+        assert_eq!(paper.dimensions(), (5120, 1440));
+        let (x0, y0, x1, y1) = globe_bounds(&paper);
+        assert_eq!(diameter((5120, 1440), &options, MORNING), 1296.0);
+        assert_near(y1 - y0 + 1, 1296, 4);
+        assert_near((x0 + x1) / 2, 2560, 2);
+        assert_near((y0 + y1) / 2, 720, 2);
+        for (x, y) in [(0, 720), (5119, 720), (x0 - 2, 720), (x1 + 2, 720)]{
+            assert!(is_background(&paper, x, y), "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn landscape_half_shows_top_of_globe(){
+        for (screen, top) in [((1920, 1080), 54), ((5120, 1440), 72)]{
+            let options = options(true, LayoutConfig::default());
+            let paper = compose(&disk_image(1100), screen, &options, MORNING).unwrap();
+            assert_eq!(paper.dimensions(), screen);
+            let (x0, y0, x1, y1) = globe_bounds(&paper);
+            // 从屏幕高度5%处开始, 超出屏幕底部的部分不显示
+            assert_near(y0, top, 4);
+            assert_eq!(y1, screen.1 - 1);
+            assert_near((x0 + x1) / 2, screen.0 / 2, 2);
+            assert!(is_background(&paper, screen.0 / 2, y0 - 2));
+            assert_eq!(diameter(screen, &options, MORNING), screen.1 as f64);
+            assert_eq!(visible(screen, &options, MORNING), Visible{ bottom: 0.95, ..Visible::ALL });
+        }
+    }
+
+    #[test]
+    fn portrait_half_image_wider_than_paper(){
+        // 2200的图片取一半缩放到95%后仍比1080宽, 需要缩小到屏幕内而不是溢出
+        let image = disk_image(2200);
+        for side in [PortraitSide::East, PortraitSide::West]{
+            let options = options(true, LayoutConfig{ portrait_side: side, ..Default::default() });
+            let paper = compose(&image, (1080, 1920), &options, MORNING).unwrap();
+            assert_eq!(paper.dimensions(), (1080, 1920));
+            let (x0, _, x1, _) = globe_bounds(&paper);
+            assert_eq!(diameter((1080, 1920), &options, MORNING), 1920.0);
+            let visible = visible((1080, 1920), &options, MORNING);
+            if side == PortraitSide::East{
+                // 东半边靠屏幕左边
+                assert_eq!(x0, 0);
+                assert!(is_background(&paper, 1079, 960));
+                assert_eq!(visible, Visible{ left: 1.0 - PORTRAIT_HALF_WIDTH, ..Visible::ALL });
+            }else{
+                assert_eq!(x1, 1079);
+                assert!(is_background(&paper, 0, 960));
+                assert_eq!(visible, Visible{ right: PORTRAIT_HALF_WIDTH, ..Visible::ALL });
+            }
+        }
+    }
+
+    #[test]
+    fn padding_larger_than_screen(){
+        let options = options(false, LayoutConfig{ padding: 5000, ..Default::default() });
+        let paper = compose(&disk_image(550), (1920, 1080), &options, MORNING).unwrap();
+        assert_eq!(paper.dimensions(), (1920, 1080));
+        assert!(is_background(&paper, 0, 0));
+        assert!(diameter((1920, 1080), &options, MORNING) >= 1.0);
+    }
+
+    #[test]
+    fn partial_image_matches_full_image(){
+        // 竖屏半张只下载可见的瓦片, 和整张图的布局相同
+        let full = disk_image(1100);
+        let options = options(true, LayoutConfig{ portrait_side: PortraitSide::East, ..Default::default() });
+        let (x0, y0, x1, y1) = visible((1080, 1920), &options, MORNING).tiles(4);
+        let (tx, ty, tw, th) = (x0 * 275, y0 * 275, (x1 - x0) * 275, (y1 - y0) * 275);
+        assert!(tw < 1100);
+        let part = ImagePart{ image: full.image.view(tx, ty, tw, th).to_image(), x: tx, y: ty, ..full.clone() };
+        let expected = compose(&full, (1080, 1920), &options, MORNING).unwrap();
+        let paper = compose(&part, (1080, 1920), &options, MORNING).unwrap();
+        assert_eq!(globe_bounds(&paper), globe_bounds(&expected));
+    }
+
+    #[test]
+    fn partial_image_keeps_its_offset(){
+        // 只有右半边时, 地球的右半边仍画在原来的位置, 左半边显示背景
+        let full = disk_image(1100);
+        let part = ImagePart{ image: full.image.view(550, 0, 550, 1100).to_image(), x: 550, ..full.clone() };
+        let options = options(false, LayoutConfig::default());
+        let expected = globe_bounds(&compose(&full, (1920, 1080), &options, MORNING).unwrap());
+        let paper = compose(&part, (1920, 1080), &options, MORNING).unwrap();
+        let (x0, y0, x1, y1) = globe_bounds(&paper);
+        assert_near(x0, (expected.0 + expected.2) / 2, 2);
+        assert_eq!((y0, x1, y1), (expected.1, expected.2, expected.3));
+        assert!(is_background(&paper, expected.0 + 20, 540));
+    }
+
+    #[test]
+    fn sub_satellite_point_on_paper(){
+        let options = options(false, LayoutConfig::default());
+        let (x, y) = paper_position((1100, 1100), (1920, 1080), &options, MORNING, 550.0, 550.0).unwrap();
+        assert!((x - 960.0).abs() <= 1.0 && (y - 540.0).abs() <= 1.0, "{x},{y}");
+        // 留白向内偏移
+        let padded = LayoutOptions{ layout: LayoutConfig{ padding: 100, horizontal: HAnchor::Left, ..Default::default() }, ..options.clone() };
+        let (x, _) = paper_position((1100, 1100), (1920, 1080), &padded, MORNING, 550.0, 550.0).unwrap();
+        assert!((x - (100.0 + 792.0 / 2.0)).abs() <= 1.0, "{x}");
+        // 竖屏显示西半边时圆盘中心在屏幕右边附近
+        let west = LayoutOptions{ half: true, layout: LayoutConfig{ portrait_side: PortraitSide::West, ..Default::default() }, ..options.clone() };
+        let (x, _) = paper_position((1100, 1100), (1080, 1920), &west, MORNING, 550.0, 550.0).unwrap();
+        assert!(x > 900.0 && x < 1080.0, "{x}");
+        // 裁剪区域不包含星下点
+        let japan = LayoutOptions{ region: Some(Region{ west: 130.0, south: 30.0, east: 145.0, north: 45.0 }), ..options };
+        assert_eq!(paper_position((1100, 1100), (1920, 1080), &japan, MORNING, 550.0, 550.0), None);
+    }
+}
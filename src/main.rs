@@ -4,8 +4,17 @@ use anyhow::Result;
 mod config;
 mod downloader;
 mod app;
+mod background;
 mod def;
+mod export;
+mod history;
+mod layout;
+mod night;
+mod overlay;
 mod server;
+mod span;
+mod projection;
+mod region;
 mod ui;
 
 
@@ -0,0 +1,171 @@
+use std::path::Path;
+use anyhow::Result;
+use image::RgbaImage;
+use log::info;
+use time::PrimitiveDateTime;
+
+use crate::{app::get_app_home_dir, config::Config, def::DEFAULT_NIGHT_LIGHTS_URL, downloader::{download_image, source::ImagePart}, projection::{pixel_to_lonlat, sample, Disk}};
+
+/// 太阳直射点(经度, 纬度), 单位度
+///
+/// 使用天文年历中的低精度公式, 1950~2050年间误差约0.01度
+pub fn subsolar_point(time: PrimitiveDateTime) -> (f64, f64){
+    // 距J2000.0(2000-01-01 12:00 UTC)的天数
+    let d = time.assume_utc().unix_timestamp() as f64 / 86400.0 - 10957.5;
+    let g = (357.529 + 0.98560028 * d).to_radians();
+    let q = 280.459 + 0.98564736 * d;
+    let l = (q + 1.915 * g.sin() + 0.020 * (2.0 * g).sin()).to_radians();
+    let e = (23.439 - 0.00000036 * d).to_radians();
+    let ra = (e.cos() * l.sin()).atan2(l.cos()).to_degrees();
+    let dec = (e.sin() * l.sin()).asin().to_degrees();
+    // 格林尼治平恒星时(度)
+    let gmst = (18.697374558 + 24.06570982441908 * d) * 15.0;
+    let lon = (ra - gmst).rem_euclid(360.0);
+    (if lon > 180.0 { lon - 360.0 } else { lon }, dec)
+}
+
+/// 太阳高度角(度), 负数表示在地平线以下
+pub fn sun_elevation(lon: f64, lat: f64, subsolar: (f64, f64)) -> f64{
+    let (sun_lon, sun_lat) = subsolar;
+    let (lat, sun_lat) = (lat.to_radians(), sun_lat.to_radians());
+    let cos_zenith = lat.sin() * sun_lat.sin() + lat.cos() * sun_lat.cos() * (lon - sun_lon).to_radians().cos();
+    cos_zenith.clamp(-1.0, 1.0).asin().to_degrees()
+}
+
+/// 夜间程度, 白天为0, 太阳低于地平线twilight度以下为1, 中间平滑过渡
+pub fn night_factor(elevation: f64, twilight: f64) -> f64{
+    if twilight <= 0.0{
+        return if elevation < 0.0 { 1.0 } else { 0.0 };
+    }
+    let t = (-elevation / twilight).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// 读取夜间灯光图(等经纬度投影), 未配置文件时下载NASA Black Marble并保存到app目录
+pub fn load_night_lights(cfg: &Config) -> Result<RgbaImage>{
+    if !cfg.night_lights_file.is_empty(){
+        return Ok(image::open(&cfg.night_lights_file)?.to_rgba8());
+    }
+    let path = Path::new(&get_app_home_dir()).join("night_lights.png");
+    if path.exists(){
+        return Ok(image::open(&path)?.to_rgba8());
+    }
+    info!("下载夜间灯光图:{DEFAULT_NIGHT_LIGHTS_URL}");
+    let lights = download_image(DEFAULT_NIGHT_LIGHTS_URL)?;
+    lights.save(&path)?;
+    Ok(lights)
+}
+
+/// 在全圆盘图(或其中一部分)的夜间部分叠加灯光
+pub fn apply_to_disk(part: &mut ImagePart, disk: Disk, time: PrimitiveDateTime, lights: &RgbaImage, twilight: f64){
+    let (x0, y0, width, height) = (part.x as f64, part.y as f64, part.width, part.height);
+    apply(&mut part.image, time, lights, twilight, |x, y| pixel_to_lonlat(x0 + x as f64 + 0.5, y0 + y as f64 + 0.5, disk, width, height));
+}
+
+/// 在等经纬度全球图的夜间部分叠加灯光
+pub fn apply_to_map(map: &mut RgbaImage, time: PrimitiveDateTime, lights: &RgbaImage, twilight: f64){
+    let (width, height) = map.dimensions();
+    apply(map, time, lights, twilight, |x, y|{
+        let lon = (x as f64 + 0.5) / width as f64 * 360.0 - 180.0;
+        let lat = 90.0 - (y as f64 + 0.5) / height as f64 * 180.0;
+        Some((lon, lat))
+    });
+}
+
+fn apply<F>(img: &mut RgbaImage, time: PrimitiveDateTime, lights: &RgbaImage, twilight: f64, lonlat: F)
+where
+    F: Fn(u32, u32) -> Option<(f64, f64)>,
+{
+    let subsolar = subsolar_point(time);
+    info!("太阳直射点:{:?} 时间:{time}", subsolar);
+    let (lw, lh) = (lights.width() as f64, lights.height() as f64);
+    for (x, y, pixel) in img.enumerate_pixels_mut(){
+        // 透明部分为未下载的瓦片或地球以外
+        if pixel[3] == 0{
+            continue;
+        }
+        let Some((lon, lat)) = lonlat(x, y) else { continue };
+        let f = night_factor(sun_elevation(lon, lat, subsolar), twilight);
+        if f <= 0.0{
+            continue;
+        }
+        let light = sample(lights, (lon + 180.0).rem_euclid(360.0) / 360.0 * lw, (90.0 - lat) / 180.0 * lh);
+        for c in 0..3{
+            let v = pixel[c] as f64;
+            pixel[c] = (v + f * (light[c] - v).max(0.0)).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use time::macros::datetime;
+    use super::*;
+
+    #[test]
+    fn subsolar_latitude_at_solstice_and_equinox(){
+        let (_, lat) = subsolar_point(datetime!(2024-06-20 20:51));
+        assert!((lat - 23.44).abs() < 0.05, "{lat}");
+        let (_, lat) = subsolar_point(datetime!(2024-12-21 09:21));
+        assert!((lat + 23.44).abs() < 0.05, "{lat}");
+        let (_, lat) = subsolar_point(datetime!(2024-03-20 03:06));
+        assert!(lat.abs() < 0.05, "{lat}");
+        let (_, lat) = subsolar_point(datetime!(2024-09-22 12:44));
+        assert!(lat.abs() < 0.05, "{lat}");
+    }
+
+    #[test]
+    fn subsolar_longitude_at_noon_utc(){
+        // 时差全年不超过±16分钟(约4.1度)
+        for time in [datetime!(2024-02-11 12:00), datetime!(2024-06-20 12:00), datetime!(2024-11-03 12:00)]{
+            let (lon, _) = subsolar_point(time);
+            assert!(lon.abs() < 4.5, "{time} {lon}");
+        }
+        // 4月中旬时差接近0
+        let (lon, _) = subsolar_point(datetime!(2024-04-15 12:00));
+        assert!(lon.abs() < 0.2, "{lon}");
+        // 一小时后太阳西移15度
+        let (lon2, _) = subsolar_point(datetime!(2024-04-15 13:00));
+        assert!((lon - lon2 - 15.0).abs() < 0.1, "{lon} {lon2}");
+    }
+
+    #[test]
+    fn sun_elevation_at_subsolar_point_and_antipode(){
+        let subsolar = (30.0, 10.0);
+        assert!((sun_elevation(30.0, 10.0, subsolar) - 90.0).abs() < 1e-6);
+        assert!((sun_elevation(-150.0, -10.0, subsolar) + 90.0).abs() < 1e-6);
+        assert!(sun_elevation(120.0, 0.0, (30.0, 0.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn night_factor_range(){
+        let twilight = 6.0;
+        for elevation in [0.0, 0.1, 30.0, 90.0]{
+            assert_eq!(night_factor(elevation, twilight), 0.0);
+        }
+        for elevation in [-6.0, -6.1, -45.0, -90.0]{
+            assert_eq!(night_factor(elevation, twilight), 1.0);
+        }
+        let f = night_factor(-3.0, twilight);
+        assert!(f > 0.0 && f < 1.0);
+        assert!(night_factor(-2.0, twilight) < night_factor(-4.0, twilight));
+        // 不过渡时以地平线为界
+        assert_eq!(night_factor(0.0, 0.0), 0.0);
+        assert_eq!(night_factor(-0.1, 0.0), 1.0);
+    }
+
+    #[test]
+    fn partial_disk_matches_full_disk(){
+        // 只下载了右下角瓦片时, 灯光按它在整张图中的位置叠加
+        let lights = RgbaImage::from_pixel(360, 180, image::Rgba([255, 220, 120, 255]));
+        let time = datetime!(2024-06-20 12:00);
+        let disk = Disk::geostationary(140.7);
+        let mut full = ImagePart::full(RgbaImage::from_pixel(400, 400, image::Rgba([10, 20, 30, 255])));
+        let mut part = ImagePart{ image: RgbaImage::from_pixel(200, 200, image::Rgba([10, 20, 30, 255])), x: 200, y: 200, ..full.clone() };
+        apply_to_disk(&mut full, disk, time, &lights, 6.0);
+        apply_to_disk(&mut part, disk, time, &lights, 6.0);
+        assert_eq!(part.image, image::imageops::crop_imm(&full.image, 200, 200, 200, 200).to_image());
+        // 12:00 UTC东经140.7°为夜晚, 有灯光
+        assert_ne!(*full.image.get_pixel(200, 200), image::Rgba([10, 20, 30, 255]));
+    }
+}
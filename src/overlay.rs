@@ -0,0 +1,214 @@
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use image::RgbImage;
+use log::info;
+use time::PrimitiveDateTime;
+
+use crate::config::Config;
+
+/// 未配置字体时依次尝试的系统字体, 优先使用支持中文的字体
+#[cfg(windows)]
+const SYSTEM_FONTS: &[&str] = &["C:\\Windows\\Fonts\\msyh.ttc", "C:\\Windows\\Fonts\\msyh.ttf", "C:\\Windows\\Fonts\\simhei.ttf", "C:\\Windows\\Fonts\\arial.ttf"];
+#[cfg(target_os = "android")]
+const SYSTEM_FONTS: &[&str] = &["/system/fonts/NotoSansCJK-Regular.ttc", "/system/fonts/DroidSansFallback.ttf", "/system/fonts/Roboto-Regular.ttf"];
+#[cfg(not(any(windows, target_os = "android")))]
+const SYSTEM_FONTS: &[&str] = &[
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/usr/share/fonts/wenquanyi/wqy-microhei/wqy-microhei.ttc",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+];
+
+/// 文字显示的位置
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corner{
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner{
+    pub fn from_name(name: &str) -> Corner{
+        match name{
+            "top_left" => Corner::TopLeft,
+            "top_right" => Corner::TopRight,
+            "bottom_left" => Corner::BottomLeft,
+            _ => Corner::BottomRight,
+        }
+    }
+}
+
+/// 文字图层参数
+#[derive(Clone, Debug)]
+pub struct OverlayOptions{
+    pub corner: Corner,
+    pub color: [u8; 3],
+    /// 不透明度 0~1
+    pub opacity: f32,
+    /// 字体大小(像素), 0为按壁纸高度自动选择
+    pub font_size: u32,
+    /// 字体文件, 为空时使用系统字体
+    pub font: String,
+}
+
+impl OverlayOptions{
+    pub fn from_config(cfg: &Config) -> Self{
+        Self {
+            corner: Corner::from_name(&cfg.overlay_corner),
+            color: parse_color(&cfg.overlay_color).unwrap_or([255, 255, 255]),
+            opacity: (cfg.overlay_opacity.min(100) as f32) / 100.0,
+            font_size: cfg.overlay_font_size,
+            font: cfg.overlay_font.clone(),
+        }
+    }
+}
+
+/// 解析"#RRGGBB"格式的颜色
+pub fn parse_color(s: &str) -> Result<[u8; 3]>{
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6{
+        return Err(anyhow!("颜色格式错误:{s}"));
+    }
+    let v = u32::from_str_radix(hex, 16)?;
+    Ok([(v >> 16) as u8, (v >> 8) as u8, v as u8])
+}
+
+/// 根据配置生成要显示的文字
+pub fn overlay_lines(cfg: &Config, time: PrimitiveDateTime, sub_lon: Option<f64>) -> Vec<String>{
+    let mut lines = vec![];
+    if cfg.overlay_show_satellite{
+        lines.push(format!("卫星: {}", cfg.satellite_name));
+    }
+    if cfg.overlay_show_time{
+        let utc = time.assume_utc();
+        let local = DateTime::from_timestamp(utc.unix_timestamp(), 0).map(|t| DateTime::<Local>::from(t).format("%Y-%m-%d %H:%M").to_string());
+        lines.push(format!("拍摄时间: {} (本地) / {}-{:02}-{:02} {:02}:{:02} UTC",
+            local.unwrap_or_default(), utc.year(), utc.month() as u8, utc.day(), utc.hour(), utc.minute()));
+    }
+    if cfg.overlay_show_longitude{
+        if let Some(lon) = sub_lon{
+            lines.push(format!("星下点: {:.1}°{}", lon.abs(), if lon < 0.0 { "W" } else { "E" }));
+        }
+    }
+    if !cfg.overlay_caption.is_empty(){
+        lines.push(cfg.overlay_caption.clone());
+    }
+    lines
+}
+
+/// 读取字体文件, path为空时使用系统字体
+pub fn load_font(path: &str) -> Result<FontVec>{
+    let paths: Vec<&str> = if path.is_empty(){ SYSTEM_FONTS.to_vec() }else{ vec![path] };
+    for path in paths{
+        if let Ok(data) = std::fs::read(path){
+            match FontVec::try_from_vec_and_index(data, 0){
+                Ok(font) => {
+                    info!("使用字体:{path}");
+                    return Ok(font);
+                }
+                Err(err) => info!("字体读取失败:{path} {:?}", err),
+            }
+        }
+    }
+    Err(anyhow!("没有找到可用的字体, 请在配置文件中设置overlay_font"))
+}
+
+/// 在壁纸的角落绘制多行文字, 文字下方带阴影
+///
+/// 多个显示器共用同一个字体, 由调用者读取一次后传入
+pub fn draw_overlay(paper: &mut RgbImage, lines: &[String], font: &FontVec, options: &OverlayOptions){
+    if lines.is_empty(){
+        return;
+    }
+    let size = if options.font_size > 0 { options.font_size as f32 } else { (paper.height() as f32 / 45.0).max(12.0) };
+    let font = font.as_scaled(PxScale::from(size));
+    let line_height = (font.height() + font.line_gap()).ceil();
+    let margin = size * 1.5;
+    let shadow = (size / 16.0).max(1.0);
+
+    let block_height = line_height * lines.len() as f32;
+    let top = match options.corner{
+        Corner::TopLeft | Corner::TopRight => margin,
+        Corner::BottomLeft | Corner::BottomRight => paper.height() as f32 - margin - block_height,
+    };
+    for (i, line) in lines.iter().enumerate(){
+        let width = text_width(&font, line);
+        let left = match options.corner{
+            Corner::TopLeft | Corner::BottomLeft => margin,
+            Corner::TopRight | Corner::BottomRight => paper.width() as f32 - margin - width,
+        };
+        let baseline = top + line_height * i as f32 + font.ascent();
+        draw_text(paper, &font, line, left + shadow, baseline + shadow, [0, 0, 0], options.opacity * 0.6);
+        draw_text(paper, &font, line, left, baseline, options.color, options.opacity);
+    }
+}
+
+/// 在壁纸的(x, y)处绘制星下点标记: 带深色描边的圆点
+pub fn draw_marker(paper: &mut RgbImage, x: f64, y: f64, options: &OverlayOptions){
+    let radius = (paper.height() as f64 / 300.0).max(3.0);
+    let outline = (radius / 3.0).max(1.0);
+    let outer = radius + outline;
+    let (x0, y0) = ((x - outer).floor().max(0.0) as u32, (y - outer).floor().max(0.0) as u32);
+    let (x1, y1) = ((x + outer).ceil().min(paper.width() as f64) as u32, (y + outer).ceil().min(paper.height() as f64) as u32);
+    for py in y0..y1{
+        for px in x0..x1{
+            let d = ((px as f64 + 0.5 - x).powi(2) + (py as f64 + 0.5 - y).powi(2)).sqrt();
+            // 边缘按覆盖比例抗锯齿
+            let dot = (radius + 0.5 - d).clamp(0.0, 1.0);
+            let ring = (outer + 0.5 - d).clamp(0.0, 1.0) - dot;
+            let pixel = paper.get_pixel_mut(px, py);
+            for c in 0..3{
+                let v = pixel[c] as f64 * (1.0 - ring * options.opacity as f64 * 0.6);
+                let a = dot * options.opacity as f64;
+                pixel[c] = (v * (1.0 - a) + options.color[c] as f64 * a).round() as u8;
+            }
+        }
+    }
+}
+
+fn text_width<F: Font, S: ScaleFont<F>>(font: &S, text: &str) -> f32{
+    let mut width = 0.0;
+    let mut last = None;
+    for c in text.chars(){
+        let id = font.glyph_id(c);
+        if let Some(last) = last{
+            width += font.kern(last, id);
+        }
+        width += font.h_advance(id);
+        last = Some(id);
+    }
+    width
+}
+
+fn draw_text<F: Font, S: ScaleFont<F>>(paper: &mut RgbImage, font: &S, text: &str, x: f32, y: f32, color: [u8; 3], opacity: f32){
+    let mut caret = x;
+    let mut last = None;
+    for c in text.chars(){
+        let id = font.glyph_id(c);
+        if let Some(last) = last{
+            caret += font.kern(last, id);
+        }
+        let glyph = id.with_scale_and_position(font.scale(), ab_glyph::point(caret, y));
+        caret += font.h_advance(id);
+        last = Some(id);
+        let Some(outlined) = font.outline_glyph(glyph) else { continue };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage|{
+            let px = bounds.min.x as i32 + gx as i32;
+            let py = bounds.min.y as i32 + gy as i32;
+            if px < 0 || py < 0 || px >= paper.width() as i32 || py >= paper.height() as i32{
+                return;
+            }
+            let a = coverage * opacity;
+            let pixel = paper.get_pixel_mut(px as u32, py as u32);
+            for c in 0..3{
+                pixel[c] = (pixel[c] as f32 * (1.0 - a) + color[c] as f32 * a).round() as u8;
+            }
+        });
+    }
+}
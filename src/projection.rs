@@ -0,0 +1,242 @@
+use image::{Rgba, RgbaImage};
+
+// 全圆盘图的投影, 地球圆盘都占满整张图片
+// 地球静止轨道投影参考CGMS LRIT/HRIT Global Specification 4.4节, 圆盘边缘对应地球边缘的扫描角
+// 正射投影为从无穷远处看地球, 圆盘边缘为地球半径
+
+/// 地心到卫星的距离(km)
+const SATELLITE_HEIGHT: f64 = 42164.0;
+/// 赤道半径(km)
+const EQUATOR_RADIUS: f64 = 6378.137;
+/// 极半径(km)
+const POLAR_RADIUS: f64 = 6356.7523;
+
+/// 地球边缘对应的扫描角(弧度)
+fn max_scan_angle() -> f64{
+    (EQUATOR_RADIUS / SATELLITE_HEIGHT).asin()
+}
+
+/// 全圆盘图的投影方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection{
+    /// 从地球静止轨道(42164km)看到的地球
+    Geostationary,
+    /// 从无穷远处看到的地球, 如EUMETView WMS的AUTO:42003
+    Orthographic,
+}
+
+/// 全圆盘图的星下点经度和投影方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Disk{
+    pub sub_lon: f64,
+    pub projection: Projection,
+}
+
+impl Disk{
+    pub fn geostationary(sub_lon: f64) -> Disk{
+        Disk{ sub_lon, projection: Projection::Geostationary }
+    }
+}
+
+/// 经纬度(度)转换为全圆盘图上的像素坐标, 在地球背面时返回None
+pub fn lonlat_to_pixel(lon: f64, lat: f64, disk: Disk, width: u32, height: u32) -> Option<(f64, f64)>{
+    match disk.projection{
+        Projection::Geostationary => geos_lonlat_to_pixel(lon, lat, disk.sub_lon, width, height),
+        Projection::Orthographic => ortho_lonlat_to_pixel(lon, lat, disk.sub_lon, width, height),
+    }
+}
+
+/// 全圆盘图上的像素坐标转换为经纬度(度), 不在地球上时返回None
+pub fn pixel_to_lonlat(px: f64, py: f64, disk: Disk, width: u32, height: u32) -> Option<(f64, f64)>{
+    match disk.projection{
+        Projection::Geostationary => geos_pixel_to_lonlat(px, py, disk.sub_lon, width, height),
+        Projection::Orthographic => ortho_pixel_to_lonlat(px, py, disk.sub_lon, width, height),
+    }
+}
+
+fn geos_lonlat_to_pixel(lon: f64, lat: f64, sub_lon: f64, width: u32, height: u32) -> Option<(f64, f64)>{
+    let lat = lat.to_radians();
+    let d_lon = (lon - sub_lon).to_radians();
+    let ratio = (POLAR_RADIUS * POLAR_RADIUS) / (EQUATOR_RADIUS * EQUATOR_RADIUS);
+    // 地心纬度
+    let c_lat = (ratio * lat.tan()).atan();
+    let r_l = POLAR_RADIUS / (1.0 - (1.0 - ratio) * c_lat.cos() * c_lat.cos()).sqrt();
+    let r1 = SATELLITE_HEIGHT - r_l * c_lat.cos() * d_lon.cos();
+    let r2 = -r_l * c_lat.cos() * d_lon.sin();
+    let r3 = r_l * c_lat.sin();
+    if SATELLITE_HEIGHT - r1 <= r_l * r_l / SATELLITE_HEIGHT{
+        return None;
+    }
+    let rn = (r1 * r1 + r2 * r2 + r3 * r3).sqrt();
+    let scan_x = (-r2 / r1).atan();
+    let scan_y = (-r3 / rn).asin();
+    let px = (1.0 + scan_x / max_scan_angle()) * width as f64 / 2.0;
+    let py = (1.0 + scan_y / max_scan_angle()) * height as f64 / 2.0;
+    Some((px, py))
+}
+
+fn geos_pixel_to_lonlat(px: f64, py: f64, sub_lon: f64, width: u32, height: u32) -> Option<(f64, f64)>{
+    let scan_x = (px / (width as f64 / 2.0) - 1.0) * max_scan_angle();
+    let scan_y = (py / (height as f64 / 2.0) - 1.0) * max_scan_angle();
+    let ratio = (EQUATOR_RADIUS * EQUATOR_RADIUS) / (POLAR_RADIUS * POLAR_RADIUS);
+    let (cos_x, cos_y, sin_y) = (scan_x.cos(), scan_y.cos(), scan_y.sin());
+    let a = cos_y * cos_y + ratio * sin_y * sin_y;
+    let b = SATELLITE_HEIGHT * cos_x * cos_y;
+    let sd = b * b - a * (SATELLITE_HEIGHT * SATELLITE_HEIGHT - EQUATOR_RADIUS * EQUATOR_RADIUS);
+    if sd < 0.0{
+        return None;
+    }
+    let sn = (b - sd.sqrt()) / a;
+    let s1 = SATELLITE_HEIGHT - sn * cos_x * cos_y;
+    let s2 = sn * scan_x.sin() * cos_y;
+    let s3 = -sn * sin_y;
+    let lon = s2.atan2(s1).to_degrees() + sub_lon;
+    let lat = (ratio * s3 / (s1 * s1 + s2 * s2).sqrt()).atan().to_degrees();
+    // 经度规范到[-180, 180)
+    Some(((lon + 540.0) % 360.0 - 180.0, lat))
+}
+
+fn ortho_lonlat_to_pixel(lon: f64, lat: f64, sub_lon: f64, width: u32, height: u32) -> Option<(f64, f64)>{
+    let lat = lat.to_radians();
+    let d_lon = (lon - sub_lon).to_radians();
+    // 和视线方向夹角超过90°的点在地球背面
+    if lat.cos() * d_lon.cos() < 0.0{
+        return None;
+    }
+    let x = lat.cos() * d_lon.sin();
+    let y = lat.sin();
+    Some(((1.0 + x) * width as f64 / 2.0, (1.0 - y) * height as f64 / 2.0))
+}
+
+fn ortho_pixel_to_lonlat(px: f64, py: f64, sub_lon: f64, width: u32, height: u32) -> Option<(f64, f64)>{
+    let x = px / (width as f64 / 2.0) - 1.0;
+    let y = 1.0 - py / (height as f64 / 2.0);
+    let rho2 = x * x + y * y;
+    if rho2 > 1.0{
+        return None;
+    }
+    let z = (1.0 - rho2).sqrt();
+    let lon = x.atan2(z).to_degrees() + sub_lon;
+    let lat = y.asin().to_degrees();
+    Some(((lon + 540.0) % 360.0 - 180.0, lat))
+}
+
+/// 全圆盘图上的点到地球边缘的距离(像素), 在地球内为正数
+///
+/// 地球静止轨道投影的边缘近似为椭圆, 水平半径为图片宽度的一半, 垂直半径按极半径缩小; 正射投影的边缘为圆
+pub fn limb_distance(px: f64, py: f64, projection: Projection, width: u32, height: u32) -> f64{
+    let a = width as f64 / 2.0;
+    let b = match projection{
+        Projection::Geostationary => height as f64 / 2.0 * POLAR_RADIUS / EQUATOR_RADIUS,
+        Projection::Orthographic => height as f64 / 2.0,
+    };
+    let (dx, dy) = ((px - width as f64 / 2.0) / a, (py - height as f64 / 2.0) / b);
+    (1.0 - (dx * dx + dy * dy).sqrt()) * a.min(b)
+}
+
+/// 地面点和星下点夹角的余弦, 越大越接近星下点
+pub fn view_angle_cos(lon: f64, lat: f64, sub_lon: f64) -> f64{
+    lat.to_radians().cos() * (lon - sub_lon).to_radians().cos()
+}
+
+/// 把全圆盘图投影为width x width/2的等经纬度地图(经度-180°~180°, 纬度90°~-90°)
+///
+/// 卫星看不到的区域为透明
+pub fn to_equirectangular(image: &RgbaImage, disk: Disk, width: u32) -> RgbaImage{
+    let height = width / 2;
+    let mut map = RgbaImage::new(width, height);
+    for y in 0..height{
+        let lat = 90.0 - (y as f64 + 0.5) * 180.0 / height as f64;
+        for x in 0..width{
+            let lon = (x as f64 + 0.5) * 360.0 / width as f64 - 180.0;
+            if let Some((px, py)) = lonlat_to_pixel(lon, lat, disk, image.width(), image.height()){
+                let [r, g, b] = sample(image, px, py);
+                map.put_pixel(x, y, Rgba([r as u8, g as u8, b as u8, 255]));
+            }
+        }
+    }
+    map
+}
+
+/// 双线性采样
+pub fn sample(img: &RgbaImage, x: f64, y: f64) -> [f64; 3]{
+    let x = (x - 0.5).clamp(0.0, (img.width() - 1) as f64);
+    let y = (y - 0.5).clamp(0.0, (img.height() - 1) as f64);
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(img.width() - 1), (y0 + 1).min(img.height() - 1));
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+    let (p00, p10, p01, p11) = (img.get_pixel(x0, y0), img.get_pixel(x1, y0), img.get_pixel(x0, y1), img.get_pixel(x1, y1));
+    let mut out = [0.0; 3];
+    for c in 0..3{
+        let top = p00[c] as f64 * (1.0 - fx) + p10[c] as f64 * fx;
+        let bottom = p01[c] as f64 * (1.0 - fx) + p11[c] as f64 * fx;
+        out[c] = top * (1.0 - fy) + bottom * fy;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const SIZE: u32 = 5500;
+
+    fn assert_close(a: f64, b: f64, tolerance: f64){
+        assert!((a - b).abs() <= tolerance, "{a} != {b} (±{tolerance})");
+    }
+
+    #[test]
+    fn sub_satellite_point_is_centre(){
+        for disk in [Disk::geostationary(140.7), Disk{ sub_lon: 45.5, projection: Projection::Orthographic }]{
+            let (px, py) = lonlat_to_pixel(disk.sub_lon, 0.0, disk, SIZE, SIZE).unwrap();
+            assert_close(px, SIZE as f64 / 2.0, 1e-6);
+            assert_close(py, SIZE as f64 / 2.0, 1e-6);
+        }
+    }
+
+    #[test]
+    fn equator_reaches_limb_at_81_degrees(){
+        // 地球静止轨道上能看到的最大地心角 acos(6378.137/42164) ≈ 81.3°
+        let disk = Disk::geostationary(105.0);
+        let (east, y) = lonlat_to_pixel(105.0 + 81.2, 0.0, disk, SIZE, SIZE).unwrap();
+        assert_close(east, SIZE as f64, SIZE as f64 * 0.002);
+        assert_close(y, SIZE as f64 / 2.0, 1e-6);
+        let (west, _) = lonlat_to_pixel(105.0 - 81.2, 0.0, disk, SIZE, SIZE).unwrap();
+        assert_close(west, 0.0, SIZE as f64 * 0.002);
+    }
+
+    #[test]
+    fn far_side_is_none(){
+        let disk = Disk::geostationary(0.0);
+        assert!(lonlat_to_pixel(180.0, 0.0, disk, SIZE, SIZE).is_none());
+        assert!(lonlat_to_pixel(85.0, 0.0, disk, SIZE, SIZE).is_none());
+        assert!(lonlat_to_pixel(0.0, -85.0, disk, SIZE, SIZE).is_none());
+        let ortho = Disk{ sub_lon: 0.0, projection: Projection::Orthographic };
+        assert!(lonlat_to_pixel(120.0, 10.0, ortho, SIZE, SIZE).is_none());
+        // 圆盘外的像素
+        assert!(pixel_to_lonlat(10.0, 10.0, disk, SIZE, SIZE).is_none());
+        assert!(pixel_to_lonlat(10.0, 10.0, ortho, SIZE, SIZE).is_none());
+    }
+
+    #[test]
+    fn round_trip(){
+        let disks = [Disk::geostationary(140.7), Disk::geostationary(-75.2), Disk{ sub_lon: 0.0, projection: Projection::Orthographic }];
+        for disk in disks{
+            for (dlon, lat) in [(0.0, 0.0), (30.0, 20.0), (-45.0, -35.0), (60.0, 50.0), (-70.0, 10.0), (10.0, -65.0)]{
+                let lon = (disk.sub_lon + dlon + 540.0) % 360.0 - 180.0;
+                let (px, py) = lonlat_to_pixel(lon, lat, disk, SIZE, SIZE).unwrap();
+                let (lon2, lat2) = pixel_to_lonlat(px, py, disk, SIZE, SIZE).unwrap();
+                assert_close(lon2, lon, 1e-6);
+                assert_close(lat2, lat, 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn projections_differ_away_from_centre(){
+        // 离星下点60°时两种投影的位置相差约6%的半径
+        let geos = lonlat_to_pixel(60.0, 0.0, Disk::geostationary(0.0), SIZE, SIZE).unwrap();
+        let ortho = lonlat_to_pixel(60.0, 0.0, Disk{ sub_lon: 0.0, projection: Projection::Orthographic }, SIZE, SIZE).unwrap();
+        assert!((geos.0 - ortho.0).abs() > SIZE as f64 / 2.0 * 0.03);
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{anyhow, Result};
+use log::warn;
+
+use crate::{config::Config, projection::{lonlat_to_pixel, Disk}};
+
+/// 经纬度范围(度), 东经、北纬为正
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region{
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+}
+
+/// 预设区域, 名字对应Config.region_name
+pub static REGIONS: &[(&str, Region)] = &[
+    ("east_asia", Region{ west: 70.0, south: 10.0, east: 150.0, north: 55.0 }),
+    ("china", Region{ west: 73.0, south: 17.0, east: 136.0, north: 54.0 }),
+    ("japan", Region{ west: 127.0, south: 28.0, east: 148.0, north: 46.0 }),
+    ("southeast_asia", Region{ west: 92.0, south: -11.0, east: 142.0, north: 24.0 }),
+    ("australia", Region{ west: 110.0, south: -45.0, east: 158.0, north: -9.0 }),
+    ("india", Region{ west: 66.0, south: 5.0, east: 98.0, north: 36.0 }),
+    ("europe", Region{ west: -12.0, south: 34.0, east: 42.0, north: 63.0 }),
+    ("africa", Region{ west: -20.0, south: -36.0, east: 53.0, north: 38.0 }),
+    ("north_america", Region{ west: -130.0, south: 15.0, east: -60.0, north: 55.0 }),
+    ("south_america", Region{ west: -82.0, south: -56.0, east: -34.0, north: 13.0 }),
+];
+
+impl Region{
+    /// 读取配置中的区域, region_bbox优先于region_name, 都没有时显示整个地球
+    ///
+    /// region_bbox无效时忽略, 使用region_name
+    pub fn from_config(cfg: &Config) -> Option<Region>{
+        if let Some(bbox) = cfg.region_bbox{
+            match Region::from_bbox(bbox){
+                Ok(region) => return Some(region),
+                Err(err) => warn!("region_bbox无效, 已忽略:{:?}", err),
+            }
+        }
+        REGIONS.iter().find(|(name, _)| *name == cfg.region_name).map(|(_, region)| *region)
+    }
+
+    /// 由[西经度, 南纬度, 东经度, 北纬度]构造区域, 东经度小于西经度时表示跨越180°经线
+    pub fn from_bbox(bbox: [f64; 4]) -> Result<Region>{
+        let [west, south, east, north] = bbox;
+        if !bbox.iter().all(|v| v.is_finite()){
+            return Err(anyhow!("经纬度不是有效数字:{:?}", bbox));
+        }
+        if !(-180.0..=180.0).contains(&west) || !(-180.0..=180.0).contains(&east){
+            return Err(anyhow!("经度应在-180~180之间:{:?}", bbox));
+        }
+        if !(-90.0..=90.0).contains(&south) || !(-90.0..=90.0).contains(&north){
+            return Err(anyhow!("纬度应在-90~90之间:{:?}", bbox));
+        }
+        if south >= north || west == east{
+            return Err(anyhow!("区域大小为0或南北颠倒:{:?}", bbox));
+        }
+        Ok(Region{ west, south, east, north })
+    }
+
+    /// 跨越180°经线时east小于west
+    fn width(&self) -> f64{
+        if self.east >= self.west { self.east - self.west }else{ self.east + 360.0 - self.west }
+    }
+
+    /// 区域边界上的采样点
+    fn boundary(&self) -> Vec<(f64, f64)>{
+        let steps = 32;
+        let mut points = vec![];
+        for i in 0..=steps{
+            let t = i as f64 / steps as f64;
+            let lon = self.west + self.width() * t;
+            let lat = self.south + (self.north - self.south) * t;
+            points.push((lon, self.south));
+            points.push((lon, self.north));
+            points.push((self.west, lat));
+            points.push((self.east, lat));
+        }
+        points
+    }
+
+    /// 区域在全圆盘图上的像素范围(x, y, 宽, 高), 卫星看不到该区域时返回None
+    pub fn rect_on_disk(&self, disk: Disk, width: u32, height: u32) -> Option<(u32, u32, u32, u32)>{
+        let points: Vec<(f64, f64)> = self.boundary().into_iter()
+            .filter_map(|(lon, lat)| lonlat_to_pixel(lon, lat, disk, width, height))
+            .collect();
+        bounding_rect(&points, width, height)
+    }
+
+    /// 区域在等经纬度地图上的像素范围(x, y, 宽, 高)
+    pub fn rect_on_map(&self, width: u32, height: u32) -> Option<(u32, u32, u32, u32)>{
+        let points: Vec<(f64, f64)> = self.boundary().into_iter()
+            .map(|(lon, lat)| {
+                let lon = (lon + 540.0) % 360.0 - 180.0;
+                ((lon + 180.0) / 360.0 * width as f64, (90.0 - lat) / 180.0 * height as f64)
+            })
+            .collect();
+        bounding_rect(&points, width, height)
+    }
+}
+
+fn bounding_rect(points: &[(f64, f64)], width: u32, height: u32) -> Option<(u32, u32, u32, u32)>{
+    if points.is_empty(){
+        return None;
+    }
+    let min_x = points.iter().map(|p| p.0).fold(f64::MAX, f64::min).clamp(0.0, width as f64);
+    let max_x = points.iter().map(|p| p.0).fold(f64::MIN, f64::max).clamp(0.0, width as f64);
+    let min_y = points.iter().map(|p| p.1).fold(f64::MAX, f64::min).clamp(0.0, height as f64);
+    let max_y = points.iter().map(|p| p.1).fold(f64::MIN, f64::max).clamp(0.0, height as f64);
+    let (w, h) = ((max_x - min_x) as u32, (max_y - min_y) as u32);
+    if w == 0 || h == 0{
+        return None;
+    }
+    Some((min_x as u32, min_y as u32, w, h))
+}
+
+/// 把矩形扩展到aspect(宽/高)的比例, 不超出width x height的范围
+pub fn fit_aspect(rect: (u32, u32, u32, u32), aspect: f64, width: u32, height: u32) -> (u32, u32, u32, u32){
+    let (x, y, w, h) = (rect.0 as f64, rect.1 as f64, rect.2 as f64, rect.3 as f64);
+    let (cx, cy) = (x + w / 2.0, y + h / 2.0);
+    let (mut w, mut h) = if w / h < aspect { (h * aspect, h) }else{ (w, w / aspect) };
+    // 超出图片时等比缩小
+    if w > width as f64{
+        h = h * width as f64 / w;
+        w = width as f64;
+    }
+    if h > height as f64{
+        w = w * height as f64 / h;
+        h = height as f64;
+    }
+    let x = (cx - w / 2.0).clamp(0.0, width as f64 - w);
+    let y = (cy - h / 2.0).clamp(0.0, height as f64 - h);
+    (x as u32, y as u32, (w as u32).max(1), (h as u32).max(1))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn config(name: &str, bbox: Option<[f64; 4]>) -> Config{
+        Config{ region_name: name.to_string(), region_bbox: bbox, ..Config::default() }
+    }
+
+    #[test]
+    fn region_from_config(){
+        assert_eq!(Region::from_config(&config("", None)), None);
+        assert_eq!(Region::from_config(&config("atlantis", None)), None);
+        assert_eq!(Region::from_config(&config("japan", None)), Some(Region{ west: 127.0, south: 28.0, east: 148.0, north: 46.0 }));
+        // bbox优先
+        let bbox = [100.0, -10.0, 120.0, 5.0];
+        assert_eq!(Region::from_config(&config("japan", Some(bbox))), Some(Region{ west: 100.0, south: -10.0, east: 120.0, north: 5.0 }));
+        // bbox无效时使用名字
+        assert_eq!(Region::from_config(&config("japan", Some([100.0, 20.0, 120.0, 10.0]))), Region::from_config(&config("japan", None)));
+    }
+
+    #[test]
+    fn bbox_validation(){
+        assert!(Region::from_bbox([70.0, 10.0, 150.0, 55.0]).is_ok());
+        // 跨越180°经线
+        assert!(Region::from_bbox([170.0, -50.0, -170.0, -30.0]).is_ok());
+        for bbox in [
+            [70.0, 55.0, 150.0, 10.0],
+            [70.0, 10.0, 70.0, 55.0],
+            [-190.0, 10.0, 150.0, 55.0],
+            [70.0, 10.0, 150.0, 95.0],
+            [f64::NAN, 10.0, 150.0, 55.0],
+        ]{
+            assert!(Region::from_bbox(bbox).is_err(), "{:?}", bbox);
+        }
+    }
+
+    #[test]
+    fn rect_on_disk_and_map(){
+        let japan = Region::from_config(&config("japan", None)).unwrap();
+        // 向日葵8号能看到日本, 在圆盘中线上方, 横跨星下点经线
+        let (x, y, w, h) = japan.rect_on_disk(Disk::geostationary(140.7), 1000, 1000).unwrap();
+        assert!(x < 500 && x + w > 500 && y + h < 500, "{x},{y} {w}x{h}");
+        // GOES东星看不到日本
+        assert_eq!(japan.rect_on_disk(Disk::geostationary(-75.2), 1000, 1000), None);
+        assert_eq!(japan.rect_on_map(3600, 1800), Some((3070, 440, 210, 180)));
+    }
+
+    #[test]
+    fn fit_aspect_widens_and_clamps(){
+        // 100x100扩展为16:9, 中心不变
+        assert_eq!(fit_aspect((450, 450, 100, 100), 16.0 / 9.0, 1000, 1000), (411, 450, 177, 100));
+        // 靠近边缘时移回图片内
+        assert_eq!(fit_aspect((0, 0, 100, 100), 2.0, 1000, 1000), (0, 0, 200, 100));
+        // 超出图片时等比缩小
+        assert_eq!(fit_aspect((0, 400, 1000, 200), 0.5, 1000, 1000), (250, 0, 500, 1000));
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Result};
+use image::{GenericImageView, RgbImage};
+
+use crate::{app::Monitor, downloader::fast_resize};
+
+/// 多显示器横跨模式: 所有显示器组成一张大画布, 每个显示器显示其中对应的部分
+#[derive(Clone, Debug)]
+pub struct SpanLayout{
+    /// 画布大小(像素)
+    pub width: u32,
+    pub height: u32,
+    /// 每个显示器在画布中的位置 (x, y, w, h)
+    pub rects: Vec<(u32, u32, u32, u32)>,
+}
+
+impl SpanLayout{
+    /// 按显示器在虚拟桌面中的位置计算画布
+    ///
+    /// bezel为相邻显示器之间边框的宽度(桌面坐标单位), 画布按像素密度最高的显示器放大
+    pub fn new(monitors: &[Monitor], bezel: u32) -> SpanLayout{
+        let scale = monitors.iter().map(|m| m.scale).fold(1.0, f64::max);
+        // 桌面坐标中的位置和大小, 左边/上边每有一列/一行显示器就多留一个边框
+        let boxes: Vec<(f64, f64, f64, f64)> = monitors.iter().map(|m|{
+            let w = m.width as f64 / m.scale;
+            let h = m.height as f64 / m.scale;
+            let cols = monitors.iter().filter(|o| (o.x as f64 + o.width as f64 / o.scale) <= m.x as f64).count();
+            let rows = monitors.iter().filter(|o| (o.y as f64 + o.height as f64 / o.scale) <= m.y as f64).count();
+            (m.x as f64 + (cols as u32 * bezel) as f64, m.y as f64 + (rows as u32 * bezel) as f64, w, h)
+        }).collect();
+        let left = boxes.iter().map(|b| b.0).fold(f64::MAX, f64::min);
+        let top = boxes.iter().map(|b| b.1).fold(f64::MAX, f64::min);
+        let right = boxes.iter().map(|b| b.0 + b.2).fold(f64::MIN, f64::max);
+        let bottom = boxes.iter().map(|b| b.1 + b.3).fold(f64::MIN, f64::max);
+        let width = ((right - left) * scale).round().max(1.0) as u32;
+        let height = ((bottom - top) * scale).round().max(1.0) as u32;
+        let rects = boxes.iter().map(|(x, y, w, h)|{
+            let x = (((x - left) * scale).round() as u32).min(width - 1);
+            let y = (((y - top) * scale).round() as u32).min(height - 1);
+            let w = ((w * scale).round() as u32).clamp(1, width - x);
+            let h = ((h * scale).round() as u32).clamp(1, height - y);
+            (x, y, w, h)
+        }).collect();
+        SpanLayout{ width, height, rects }
+    }
+
+    /// 从画布中裁剪出每个显示器的壁纸, 并缩放到显示器的实际像素
+    pub fn slice(&self, canvas: &RgbImage, monitors: &[Monitor]) -> Result<Vec<RgbImage>>{
+        if canvas.dimensions() != (self.width, self.height){
+            return Err(anyhow!("画布大小错误: {:?} {}x{}", canvas.dimensions(), self.width, self.height));
+        }
+        Ok(monitors.iter().zip(self.rects.iter()).map(|(monitor, (x, y, w, h))|{
+            let part = canvas.view(*x, *y, *w, *h).to_image();
+            if part.dimensions() == (monitor.width, monitor.height){
+                part
+            }else{
+                fast_resize(&part, monitor.width, monitor.height)
+            }
+        }).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use image::Rgb;
+    use super::*;
+
+    fn monitor(x: i32, y: i32, width: u32, height: u32, scale: f64) -> Monitor{
+        Monitor{ id: format!("{x},{y}"), x, y, width, height, scale }
+    }
+
+    /// 每个像素的颜色记录它在画布中的位置
+    fn canvas(span: &SpanLayout) -> RgbImage{
+        RgbImage::from_fn(span.width, span.height, |x, y| Rgb([(x % 256) as u8, (x / 256) as u8, (y % 256) as u8]))
+    }
+
+    #[test]
+    fn side_by_side_with_bezel_gap(){
+        let monitors = [monitor(0, 0, 1920, 1080, 1.0), monitor(1920, 0, 1920, 1080, 1.0)];
+        let span = SpanLayout::new(&monitors, 40);
+        // 两个显示器之间留出边框的宽度
+        assert_eq!((span.width, span.height), (3880, 1080));
+        assert_eq!(span.rects, vec![(0, 0, 1920, 1080), (1960, 0, 1920, 1080)]);
+        let canvas = canvas(&span);
+        let papers = span.slice(&canvas, &monitors).unwrap();
+        assert_eq!(papers[0].dimensions(), (1920, 1080));
+        assert_eq!(papers[1].dimensions(), (1920, 1080));
+        assert_eq!(papers[0].get_pixel(1919, 10), canvas.get_pixel(1919, 10));
+        // 第二个显示器从边框之后开始, 边框部分不显示
+        assert_eq!(papers[1].get_pixel(0, 10), canvas.get_pixel(1960, 10));
+        assert!(span.slice(&RgbImage::new(100, 100), &monitors).is_err());
+    }
+
+    #[test]
+    fn vertical_offset_and_mixed_resolution(){
+        // 右边的显示器下沉180, 物理分辨率2560x1440, 缩放200%
+        let monitors = [monitor(0, 0, 1280, 720, 1.0), monitor(1280, 180, 2560, 1440, 2.0)];
+        let span = SpanLayout::new(&monitors, 20);
+        // 画布按像素密度最高的显示器放大
+        assert_eq!((span.width, span.height), ((1280 + 20 + 1280) * 2, (180 + 720) * 2));
+        assert_eq!(span.rects, vec![(0, 0, 2560, 1440), (2600, 360, 2560, 1440)]);
+        let canvas = canvas(&span);
+        let papers = span.slice(&canvas, &monitors).unwrap();
+        // 低密度的显示器缩小到实际像素
+        assert_eq!(papers[0].dimensions(), (1280, 720));
+        assert_eq!(papers[1].dimensions(), (2560, 1440));
+        assert_eq!(papers[1].get_pixel(0, 0), canvas.get_pixel(2600, 360));
+    }
+}
@@ -1,5 +1,11 @@
 slint::slint!{
-    import { TabWidget , VerticalBox, ComboBox, HorizontalBox, Button} from "std-widgets.slint";
+    import { TabWidget , VerticalBox, ComboBox, HorizontalBox, Button, LineEdit, SpinBox, ScrollView} from "std-widgets.slint";
+
+    export struct HistoryItem {
+        thumbnail: image,
+        timestr: string,
+        path: string,
+    }
 
     export component Main inherits Window {
         title: "卫星壁纸";
@@ -11,13 +17,39 @@ slint::slint!{
         in-out property <string> wallpaper_file: "";
         in property <string> f4a_data_url: "";
         in property <string> h8_data_url: "";
+        in property <string> goes_data_url: "";
+        in property <string> meteosat_data_url: "";
         in property <string> config_file: "";
 
         in-out property <int> current-satellite-index: 0;
+        property <[string]> satellite-names: ["卫星：风云4号", "卫星：向日葵8号", "卫星：GOES东星", "卫星：GOES西星", "卫星：Meteosat 0°", "卫星：Meteosat 印度洋", "卫星：全球拼接"];
         in-out property <int> current-interval-index: 0;
         in-out property <int> current-size-index: 0;
         in-out property <bool> is-startup: false;
+        // 0:最新 1:固定时间 2:N天前
+        in-out property <int> current-time-mode-index: 0;
+        // 0:不显示 1:左上 2:右上 3:左下 4:右下
+        in-out property <int> current-overlay-index: 0;
+        in-out property <bool> span-monitors: false;
+        // 布局: 当前壁纸样式的缩放百分比、留白、背景颜色
+        in-out property <int> layout-scale: 90;
+        in-out property <int> layout-padding: 0;
+        in-out property <string> layout-background: "#000000";
+        in-out property <string> layout-background-gradient: "";
+        // 0:居左 1:居中 2:居右
+        in-out property <int> layout-horizontal-index: 1;
+        // 0:居上 1:居中 2:居下
+        in-out property <int> layout-vertical-index: 1;
+        // 0:按时间 1:东半边 2:西半边
+        in-out property <int> portrait-side-index: 0;
+        // 0:纯色/渐变 1:星空 2:模糊地球 3:自定义图片
+        in-out property <int> background-style-index: 0;
+        in-out property <string> background-image: "";
+        in-out property <string> pinned-time: "";
+        in-out property <int> days-ago: 1;
         in-out property <string> download_status: "下载状态:";
+        in property <[HistoryItem]> history-items;
+        in property <string> history_status: "";
         out property <int> tab-index: 0;
         in-out property <int> old-tab-index: 99;
 
@@ -25,11 +57,28 @@ slint::slint!{
         callback change_interval(int);
         callback change_wallpaper_size(int);
         callback change_startup(bool);
+        callback change_overlay(int);
+        callback change_span_monitors(bool);
+        callback change_layout_scale(int);
+        callback change_layout_padding(int);
+        callback change_layout_horizontal(int);
+        callback change_layout_vertical(int);
+        callback change_portrait_side(int);
+        callback change_layout_background(string, string);
+        callback change_background_style(int);
+        callback change_background_image(string);
+        callback change_time_mode(int);
+        callback change_pinned_time(string);
+        callback change_days_ago(int);
         callback sync_now();
         callback open_home_page();
         callback open_gitee_page();
         callback open_github_page();
         callback open_image_file();
+        callback refresh_history();
+        callback restore_history(string);
+        callback open_history(string);
+        callback export_history(string);
 
         Rectangle {
             TabWidget {
@@ -69,66 +118,311 @@ slint::slint!{
                     title: "　　　　设置 ⛭　　　　";
                     Rectangle {
                         background: #202020;
-                        VerticalBox {
-                            ComboBox {
-                                model: ["卫星：风云4号", "卫星：向日葵8号"];
-                                current-value: "卫星："+(current-satellite-index==0?"风云4号":"向日葵8号");
-                                current-index: current-satellite-index;
-                                selected => {
-                                    change-satellite(self.current-index)
+                        settings-view := ScrollView {
+                            content-width: self.visible-width;
+                            content-height: settings-box.preferred-height;
+                            settings-box := VerticalBox {
+                                width: settings-view.visible-width;
+                                ComboBox {
+                                    model: satellite-names;
+                                    current-value: satellite-names[current-satellite-index];
+                                    current-index: current-satellite-index;
+                                    selected => {
+                                        change-satellite(self.current-index)
+                                    }
                                 }
-                            }
-                            ComboBox {
-                                model: ["更新频率：10分钟", "更新频率：20分钟", "更新频率：30分钟", "更新频率：40分钟", "更新频率：50分钟", "更新频率：60分钟"];
-                                current-value: "更新频率："+((current-interval-index+1) * 10)+"分钟";
-                                current-index: current-interval-index;
-                                selected => {
-                                    change-interval(self.current-index)
+                                ComboBox {
+                                    model: ["更新频率：10分钟", "更新频率：20分钟", "更新频率：30分钟", "更新频率：40分钟", "更新频率：50分钟", "更新频率：60分钟"];
+                                    current-value: "更新频率："+((current-interval-index+1) * 10)+"分钟";
+                                    current-index: current-interval-index;
+                                    selected => {
+                                        change-interval(self.current-index)
+                                    }
                                 }
-                            }
-                            ComboBox {
-                                model: ["壁纸样式：整张", "壁纸样式：半张"];
-                                current-value: "壁纸样式："+(current-size-index==0?"整张":"半张");
-                                current-index: current-size-index;
-                                selected => {
-                                    change_wallpaper_size(self.current-index)
+                                ComboBox {
+                                    model: ["壁纸样式：整张", "壁纸样式：半张"];
+                                    current-value: "壁纸样式："+(current-size-index==0?"整张":"半张");
+                                    current-index: current-size-index;
+                                    selected => {
+                                        change_wallpaper_size(self.current-index)
+                                    }
                                 }
-                            }
-                            ComboBox {
-                                model: ["开机启动：否", "开机启动：是"];
-                                current-value: "开机启动："+(is-startup?"是":"否");
-                                current-index: is-startup? 1: 0;
-                                selected => {
-                                    change-startup(self.current-index==1)
+                                HorizontalBox {
+                                    padding: 0;
+                                    Text {
+                                        vertical-alignment: center;
+                                        text: "地球大小(%):";
+                                    }
+                                    SpinBox {
+                                        minimum: 10;
+                                        maximum: 100;
+                                        value: layout-scale;
+                                        edited(value) => {
+                                            layout-scale = value;
+                                            change-layout-scale(value)
+                                        }
+                                    }
+                                    Text {
+                                        vertical-alignment: center;
+                                        text: "留白(像素):";
+                                    }
+                                    SpinBox {
+                                        minimum: 0;
+                                        maximum: 1000;
+                                        value: layout-padding;
+                                        edited(value) => {
+                                            layout-padding = value;
+                                            change-layout-padding(value)
+                                        }
+                                    }
+                                }
+                                HorizontalBox {
+                                    padding: 0;
+                                    ComboBox {
+                                        model: ["水平位置：居左", "水平位置：居中", "水平位置：居右"];
+                                        current-value: self.model[layout-horizontal-index];
+                                        current-index: layout-horizontal-index;
+                                        selected => {
+                                            change-layout-horizontal(self.current-index)
+                                        }
+                                    }
+                                    ComboBox {
+                                        model: ["垂直位置：居上", "垂直位置：居中", "垂直位置：居下"];
+                                        current-value: self.model[layout-vertical-index];
+                                        current-index: layout-vertical-index;
+                                        selected => {
+                                            change-layout-vertical(self.current-index)
+                                        }
+                                    }
+                                }
+                                ComboBox {
+                                    model: ["竖屏半张：上午东半边/下午西半边", "竖屏半张：总是东半边", "竖屏半张：总是西半边"];
+                                    current-value: self.model[portrait-side-index];
+                                    current-index: portrait-side-index;
+                                    selected => {
+                                        change-portrait-side(self.current-index)
+                                    }
+                                }
+                                HorizontalBox {
+                                    padding: 0;
+                                    Text {
+                                        vertical-alignment: center;
+                                        text: "背景颜色:";
+                                    }
+                                    LineEdit {
+                                        placeholder-text: "#000000";
+                                        text <=> layout-background;
+                                        accepted(text) => {
+                                            change-layout-background(layout-background, layout-background-gradient)
+                                        }
+                                    }
+                                    LineEdit {
+                                        placeholder-text: "渐变底部颜色(可选)";
+                                        text <=> layout-background-gradient;
+                                        accepted(text) => {
+                                            change-layout-background(layout-background, layout-background-gradient)
+                                        }
+                                    }
+                                    Button {
+                                        text: "应用";
+                                        clicked => {
+                                            change-layout-background(layout-background, layout-background-gradient)
+                                        }
+                                    }
+                                }
+                                ComboBox {
+                                    model: ["背景：纯色/渐变", "背景：星空", "背景：模糊地球", "背景：自定义图片"];
+                                    current-value: self.model[background-style-index];
+                                    current-index: background-style-index;
+                                    selected => {
+                                        change-background-style(self.current-index)
+                                    }
+                                }
+                                if background-style-index == 3 : HorizontalBox {
+                                    padding: 0;
+                                    Text {
+                                        vertical-alignment: center;
+                                        text: "图片路径:";
+                                    }
+                                    LineEdit {
+                                        text <=> background-image;
+                                        accepted(text) => {
+                                            change-background-image(text)
+                                        }
+                                    }
+                                    Button {
+                                        text: "应用";
+                                        clicked => {
+                                            change-background-image(background-image)
+                                        }
+                                    }
+                                }
+                                ComboBox {
+                                    model: ["多显示器：分别显示", "多显示器：横跨显示"];
+                                    current-value: self.model[span-monitors ? 1 : 0];
+                                    current-index: span-monitors ? 1 : 0;
+                                    selected => {
+                                        change-span-monitors(self.current-index == 1)
+                                    }
+                                }
+                                ComboBox {
+                                    model: ["文字信息：不显示", "文字信息：左上角", "文字信息：右上角", "文字信息：左下角", "文字信息：右下角"];
+                                    current-value: self.model[current-overlay-index];
+                                    current-index: current-overlay-index;
+                                    selected => {
+                                        change-overlay(self.current-index)
+                                    }
+                                }
+                                ComboBox {
+                                    model: ["壁纸时间：最新", "壁纸时间：固定UTC时间", "壁纸时间：N天前同一时刻"];
+                                    current-value: self.model[current-time-mode-index];
+                                    current-index: current-time-mode-index;
+                                    selected => {
+                                        change-time-mode(self.current-index)
+                                    }
+                                }
+                                if current-time-mode-index == 1 : HorizontalBox {
+                                    padding: 0;
+                                    Text {
+                                        vertical-alignment: center;
+                                        text: "UTC时间:";
+                                    }
+                                    LineEdit {
+                                        placeholder-text: "2024-09-06 06:00";
+                                        text <=> pinned-time;
+                                        accepted(text) => {
+                                            change-pinned-time(text)
+                                        }
+                                    }
+                                    Button {
+                                        text: "应用";
+                                        clicked => {
+                                            change-pinned-time(pinned-time)
+                                        }
+                                    }
+                                }
+                                if current-time-mode-index == 2 : HorizontalBox {
+                                    padding: 0;
+                                    Text {
+                                        vertical-alignment: center;
+                                        text: "天数:";
+                                    }
+                                    SpinBox {
+                                        minimum: 1;
+                                        maximum: 3650;
+                                        value: days-ago;
+                                        edited(value) => {
+                                            days-ago = value;
+                                            change-days-ago(value)
+                                        }
+                                    }
+                                }
+                                ComboBox {
+                                    model: ["开机启动：否", "开机启动：是"];
+                                    current-value: "开机启动："+(is-startup?"是":"否");
+                                    current-index: is-startup? 1: 0;
+                                    selected => {
+                                        change-startup(self.current-index==1)
+                                    }
+                                }
+                                Text {
+                                    text: "当前壁纸:"+current_wallpaper;
+                                }
+                                Text {
+                                    text: "本地文件:"+wallpaper_file;
+                                }
+                                Text {
+                                    text: "风云4号A星数据地址:"+f4a_data_url;
+                                }
+                                Text {
+                                    text: "向日葵8号数据地址:"+h8_data_url;
+                                }
+                                Text {
+                                    text: "GOES卫星数据地址:"+goes_data_url;
+                                }
+                                Text {
+                                    text: "Meteosat卫星数据地址:"+meteosat_data_url;
+                                }
+                                Text {
+                                    text: "配置文件:"+config_file;
+                                }
+                                HorizontalBox {
+                                    alignment: center;
+                                    Text {
+                                        color: orange;
+                                        text <=> download_status;
+                                    }
+                                }
+                                Button {
+                                    width: 100%;
+                                    text: "立即更新壁纸🔄";
+                                    clicked => {
+                                        sync-now()
+                                    }
                                 }
                             }
-                            Text {
-                                text: "当前壁纸:"+current_wallpaper;
-                            }
-                            Text {
-                                text: "本地文件:"+wallpaper_file;
-                            }
-                            Text {
-                                text: "风云4号A星数据地址:"+f4a_data_url;
-                            }
-                            Text {
-                                text: "向日葵8号数据地址:"+h8_data_url;
-                            }
-                            Text {
-                                text: "配置文件:"+config_file;
+                        }
+                    }
+                }
+                Tab {
+                    title: "　　　　历史 🕘　　　　";
+                    Rectangle {
+                        background: #202020;
+                        VerticalBox {
+                            ScrollView {
+                                content-width: history-row.preferred-width;
+                                content-height: history-row.preferred-height;
+                                history-row := HorizontalLayout {
+                                    spacing: 10px;
+                                    padding: 10px;
+                                    for item in history-items : VerticalLayout {
+                                        width: 200px;
+                                        spacing: 5px;
+                                        Image {
+                                            width: 200px;
+                                            height: 120px;
+                                            image-fit: contain;
+                                            source: item.thumbnail;
+                                        }
+                                        Text {
+                                            width: 200px;
+                                            wrap: word-wrap;
+                                            font-size: 11px;
+                                            text: item.timestr;
+                                        }
+                                        Button {
+                                            text: "设为壁纸";
+                                            clicked => {
+                                                restore-history(item.path)
+                                            }
+                                        }
+                                        Button {
+                                            text: "打开";
+                                            clicked => {
+                                                open-history(item.path)
+                                            }
+                                        }
+                                        Button {
+                                            text: "导出";
+                                            clicked => {
+                                                export-history(item.path)
+                                            }
+                                        }
+                                    }
+                                }
                             }
                             HorizontalBox {
                                 alignment: center;
                                 Text {
                                     color: orange;
-                                    text <=> download_status;
+                                    text: history_status;
                                 }
                             }
                             Button {
                                 width: 100%;
-                                text: "立即更新壁纸🔄";
+                                text: "刷新🔄";
                                 clicked => {
-                                    sync-now()
+                                    refresh-history()
                                 }
                             }
                         }